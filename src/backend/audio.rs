@@ -1,10 +1,17 @@
 use std::thread::JoinHandle;
 use std::thread::spawn;
-use std::thread::sleep;
-use std::io::Cursor;
-use std::io::Read;
+use std::io::BufReader;
 use std::fs::File;
 use std::time::Duration;
+use std::time::Instant;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::sync::atomic::AtomicUsize;
+use std::sync::atomic::Ordering;
+use std::sync::mpsc::Receiver as EventReceiver;
+use std::sync::mpsc::RecvTimeoutError;
+use std::sync::mpsc::SyncSender;
+use std::sync::mpsc::sync_channel;
 use std::default::Default;
 
 use async_channel::Receiver;
@@ -12,61 +19,86 @@ use async_channel::Sender;
 use async_channel::bounded;
 
 use rodio::Decoder;
-use rodio::OutputStream;
-use rodio::OutputStreamHandle;
 use rodio::Sink;
+use rodio::Source;
+use rodio::source::EmptyCallback;
+
+use rand::Rng;
+use rand::seq::SliceRandom;
 
 use crate::backend::music::Song;
 use crate::backend::error::ResonateError;
+use crate::backend::database_manager::DataLink;
+use crate::backend::database_interface::DatabaseInterface;
+use crate::backend::loudness::Normalisation;
+use crate::backend::loudness::blocking_gain;
+use crate::backend::equalizer::Equalizer;
+use crate::backend::equalizer::EqualizerControl;
+use crate::backend::equalizer::Gains;
+use crate::backend::tempo::Tempo;
+use crate::backend::tempo::TempoControl;
+use crate::backend::tempo::Playhead;
+use crate::backend::settings::Settings;
+use crate::backend::output::Output;
+use crate::backend::output::OutputBackend;
+use crate::backend::radio::Radio;
+use crate::backend::listening::ListenTracker;
+use crate::backend::visualiser::Analyser;
+use crate::backend::visualiser::Spectrum;
+use crate::backend::visualiser::Tap;
+use crate::backend::visualiser::VisualUpdate;
+use crate::backend::looper::LoopControl;
+use crate::backend::looper::Looper;
+use crate::backend::looper::MIN_LOOP_LENGTH;
 
 #[derive(Debug, Clone, Default)]
 pub struct QueueFramework {
     pub songs: Vec<Song>,
     pub position: usize,
+    pub up_next: usize,                 // Songs straight after position that the user queued with play next
     pub playing: bool,
     pub repeat: bool,
+    pub shuffle: bool,
+    pub sleep_until: Option<Instant>,
+    pub stop_after_current: bool,
+    pub autoplay: bool,
+    pub loop_section: Option<(f32, f32)>,
 }
 
+/// Size of the read buffer each playing song streams through
+const STREAM_BUFFER: usize = 64 * 1024;
+
 pub struct QueueItem {
-    song: Song,
-    audio: Option<Cursor<Vec<u8>>>
+    song: Song
 }
 
 impl QueueItem {
     pub fn new(song: Song) -> Option<Self> {
         if song.music_path.is_some() {
-            Some(Self { song, audio: None })
+            Some(Self { song })
         } else {
             None
         }
     }
 
-    pub fn load(&mut self) {
-        let path = match self.song.music_path.as_ref() {
-            Some(path) => path.as_path(),
-            None => return
-        };
-
-        let mut file = match File::open(path) {
-            Ok(file) => file,
-            Err(_) => return
-        };
-
-        let mut buf = Vec::new();
-
-        match file.read_to_end(&mut buf) {
-            Ok(_) => {},
-            Err(_) => return
-        };
-
-        self.audio = Some(Cursor::new(buf));
+    /// Open the file for streaming, only a small buffer is held in memory at any time
+    pub fn open(&self) -> Option<BufReader<File>> {
+        let path = self.song.music_path.as_ref()?;
+        let file = File::open(path).ok()?;
+        Some(BufReader::with_capacity(STREAM_BUFFER, file))
     }
 }
 
 pub struct Queue {
     songs: Vec<QueueItem>,
     position: usize,
+    up_next: usize,
     repeat: bool,
+    shuffle: bool,
+    sleep_until: Option<Instant>,
+    stop_after_current: bool,
+    autoplay: bool,
+    loop_section: Option<(f32, f32)>,
 }
 
 impl Queue {
@@ -74,11 +106,169 @@ impl Queue {
         Queue {
             songs: Vec::new(),
             position: 0,
+            up_next: 0,
             repeat: false,
+            shuffle: false,
+            sleep_until: None,
+            stop_after_current: false,
+            autoplay: false,
+            loop_section: None,
+        }
+    }
+
+    /// Index just past the up next section, which always sits straight after the current song
+    fn up_next_end(&self) -> usize {
+        (self.position + 1 + self.up_next).min(self.songs.len())
+    }
+
+    /// Shuffle everything after the current song, leaving up next in the order the user chose
+    fn shuffle_upcoming(&mut self) {
+        let start = self.up_next_end();
+        if let Some(upcoming) = self.songs.get_mut(start..) {
+            upcoming.shuffle(&mut rand::rng());
+        }
+    }
+
+    /// Where a newly pushed song goes, somewhere after up next when shuffling
+    fn push_index(&self) -> usize {
+        match self.shuffle && !self.songs.is_empty() {
+            true => rand::rng().random_range(self.up_next_end()..=self.songs.len()),
+            false => self.songs.len()
         }
     }
+
+    /// Autoplay is on and the song playing is the last one
+    fn wants_radio(&self) -> bool {
+        self.autoplay && !self.repeat && self.position + 1 >= self.songs.len()
+    }
+
+    /// Play next: goes to the back of the up next section
+    fn insert_next(&mut self, queue_item: QueueItem) {
+        if self.songs.is_empty() {
+            self.songs.push(queue_item);
+            return;
+        }
+
+        let idx = self.up_next_end();
+        self.songs.insert(idx, queue_item);
+        self.up_next += 1;
+    }
+
+    /// Make `target` the current song. Up next songs that were skipped over stay queued
+    /// straight after the new position rather than being lost in the context.
+    fn jump(&mut self, target: usize) {
+        let end = self.up_next_end();
+        if target > self.position && target < end {
+            self.up_next = end - target - 1;
+            self.position = target;
+            return;
+        }
+
+        let start = (self.position + 1).min(end);
+        let up_next: Vec<QueueItem> = self.songs.drain(start..end).collect();
+        self.position = if target >= end { target - up_next.len() } else { target };
+        let idx = (self.position + 1).min(self.songs.len());
+        self.songs.splice(idx..idx, up_next);
+    }
+
+    /// Removing the current song makes the one after it current, or the one before if it was last
+    fn remove(&mut self, idx: usize) -> QueueItem {
+        if idx < self.position {
+            self.position -= 1;
+        } else if self.up_next > 0 && idx < self.up_next_end() {
+            // Either an up next song or the current one, whose place the first up next song takes
+            self.up_next -= 1;
+        }
+        let item = self.songs.remove(idx);
+        self.position = self.position.min(self.songs.len().saturating_sub(1));
+        item
+    }
+
+    /// Reorder a song. Dropping it inside the up next section makes it part of up next.
+    fn move_item(&mut self, from: usize, to: usize) -> bool {
+        if from == to || from >= self.songs.len() || to >= self.songs.len() {
+            return false;
+        }
+
+        let was_up_next = from > self.position && from < self.up_next_end();
+
+        if from == self.position {
+            let item = self.songs.remove(from);
+            self.songs.insert(to, item);
+            self.position = to;
+            self.up_next = 0;
+            return true;
+        }
+
+        let item = self.remove(from);
+        let end = self.position + 1 + self.up_next;
+
+        if to <= self.position {
+            self.position += 1;
+        } else if to < end || (to == end && was_up_next) {
+            self.up_next += 1;
+        }
+
+        self.songs.insert(to, item);
+        true
+    }
+
+    fn snapshot(&self, offset: f32) -> QueueSnapshot {
+        QueueSnapshot {
+            songs: self.songs.iter().map(|qi| qi.song.clone()).collect(),
+            position: self.position,
+            repeat: self.repeat,
+            shuffle: self.shuffle,
+            offset
+        }
+    }
+}
+
+/// Everything needed to put the queue back the way it was after a restart
+#[derive(Debug, Clone, PartialEq)]
+pub struct QueueSnapshot {
+    pub songs: Vec<Song>,
+    pub position: usize,
+    pub repeat: bool,
+    pub shuffle: bool,
+    pub offset: f32
 }
 
+/// What the queue holds when the application starts
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum QueueStartup {
+    #[default]
+    Restore,
+    Empty,
+    Library
+}
+
+impl QueueStartup {
+    pub fn from_string(string: &str) -> Option<QueueStartup> {
+        match string.to_lowercase().as_str() {
+            "restore" => Some(QueueStartup::Restore),
+            "empty" => Some(QueueStartup::Empty),
+            "library" => Some(QueueStartup::Library),
+            _ => None
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            QueueStartup::Restore => "restore",
+            QueueStartup::Empty => "empty",
+            QueueStartup::Library => "library"
+        }
+    }
+}
+
+/// How often the queue is written to the database if it has changed
+const PERSIST_INTERVAL: Duration = Duration::from_secs(5);
+
+/// The sleep timer fades the volume out over this long before pausing
+const SLEEP_FADE: Duration = Duration::from_secs(30);
+const FADE_STEP: Duration = Duration::from_millis(100);
+
 #[derive(Debug, Clone)]
 pub enum ScrobbleRequest {
     NowPlaying(Song),
@@ -100,40 +290,106 @@ pub enum AudioTask {
     SkipForward,
     SkipBackward,
     Push(Song),
-    Insert(Song),
+    Insert(Song),                       // Play next, after anything else the user queued this way
     EndThread,
     Move(usize),
+    Seek(f32),                          // Seconds into the current song
+    SetLoop(Option<(f32, f32)>),        // Start and end seconds of the section to repeat, None clears it
+    MoveItem(usize, usize),             // Reorder a song from one index to another
     SetQueue(Vec<Song>),
     RemoveSongById(usize),
     RemoveSongByIdx(usize),
     ToggleRepeat,
+    SetShuffle(bool),
+    SetSleepTimer(Option<Duration>),     // None cancels the timer
+    SetStopAfterCurrent(bool),
+    SetAutoplay(bool),
+    Restore(QueueSnapshot),
     SetVolume(f32),
+    SetNormalisation(Normalisation),
+    SetEqualizer(Gains),
+    SetSpeed(f32),
+    SetPreservePitch(bool),
     ClearQueue
 }
 
+/// Everything that wakes the audio thread
+enum AudioEvent {
+    Task(AudioTask),
+    SourceEnded,            // The generation that finished is in `Pipeline::ended`
+    RadioPick(usize, Song)  // Generation of the song it should follow
+}
+
+/// Processing applied to every song as it is loaded into the sink
+struct Pipeline {
+    database: DataLink,
+    normalisation: Normalisation,
+    equalizer: EqualizerControl,
+    tempo: TempoControl,
+    playhead: Playhead,
+    tap: Tap,
+    looper: LoopControl,
+    radio: Radio,
+    events: SyncSender<AudioEvent>,
+    generation: usize,
+    ended: Arc<AtomicUsize>     // Generation of the last song to finish, 0 once handled
+}
+
+impl Pipeline {
+    fn new(database: DataLink, music_path: PathBuf, settings: &Settings, events: SyncSender<AudioEvent>) -> Self {
+        let picks = events.clone();
+        Self {
+            events,
+            generation: 0,
+            ended: Arc::new(AtomicUsize::new(0)),
+            radio: Radio::new(database.clone(), music_path, move |generation, song| {
+                let _ = picks.send(AudioEvent::RadioPick(generation, song));
+            }),
+            database,
+            normalisation: settings.normalisation,
+            equalizer: EqualizerControl::new(settings.equalizer),
+            tempo: TempoControl::new(settings.speed, settings.preserve_pitch),
+            playhead: Playhead::default(),
+            tap: Tap::new(),
+            looper: LoopControl::default()
+        }
+    }
+
+    /// Whether the song loaded now has finished, only true once per song
+    fn take_ended(&self) -> bool {
+        self.generation != 0 && self.ended.swap(0, Ordering::Relaxed) == self.generation
+    }
+}
+
 fn update_queue(sink: &Sink, queue: &Queue, queue_upstream: &Sender<QueueFramework>) {
     let _ = queue_upstream.send_blocking(
         QueueFramework {
             songs: queue.songs.iter().map(|qi| qi.song.clone()).collect(),
             position: queue.position,
+            up_next: queue.up_next,
             playing: !sink.is_paused(),
             repeat: queue.repeat,
+            shuffle: queue.shuffle,
+            sleep_until: queue.sleep_until,
+            stop_after_current: queue.stop_after_current,
+            autoplay: queue.autoplay,
+            loop_section: queue.loop_section,
         }
     );
 }
 
 fn load_audio(
-    sink: &Sink, queue: &mut Queue, queue_upstream: &Sender<QueueFramework>, scrobble_upstream: &Sender<ScrobbleRequest>
+    sink: &Sink, queue: &mut Queue, queue_upstream: &Sender<QueueFramework>, scrobble_upstream: &Sender<ScrobbleRequest>,
+    pipeline: &mut Pipeline, play: bool
 ) -> Option<usize> {
-    // assume position has already been adjusted
-    let changed_audio = if let Some(queue_item) = queue.songs.get_mut(queue.position) {
+    // Markers belong to the song they were set on
+    queue.loop_section = None;
+    pipeline.looper.set(None);
 
-        queue_item.load();
+    // assume position has already been adjusted
+    let changed_audio = if let Some(queue_item) = queue.songs.get(queue.position) {
 
-        let audio = match queue_item.audio.as_ref() {
-            Some(audio) => audio.clone(),
-            None => return None
-        };
+        let audio = queue_item.open()?;
 
         let decoder = match Decoder::new(audio) {
             Ok(decoder) => decoder,
@@ -143,9 +399,28 @@ fn load_audio(
             }
         };
 
+        let gain = blocking_gain(&pipeline.database, &queue_item.song, pipeline.normalisation);
+
+        // A fresh playhead so a source still draining from the last song can't move it
+        pipeline.playhead = Playhead::default();
+        let equalizer = Equalizer::new(decoder.amplify(gain), pipeline.equalizer.clone());
+
+        // Lets the audio thread sleep until the song finishes instead of polling the sink
+        pipeline.generation += 1;
+        let (events, ended, generation) = (pipeline.events.clone(), pipeline.ended.clone(), pipeline.generation);
+        let on_end = EmptyCallback::<f32>::new(Box::new(move || {
+            // Runs on the output thread so can't block. The event only wakes the audio thread,
+            // which also checks `ended` on its progress tick in case the channel was full.
+            ended.store(generation, Ordering::Relaxed);
+            let _ = events.try_send(AudioEvent::SourceEnded);
+        }));
+
         sink.clear();
-        sink.append(decoder);
-        sink.play();
+        let tempo = Tempo::new(equalizer, pipeline.tempo.clone(), pipeline.playhead.clone());
+        let looper = Looper::new(tempo, pipeline.looper.clone(), pipeline.playhead.clone());
+        sink.append(Analyser::new(looper, pipeline.tap.clone()));
+        sink.append(on_end);
+        if play { sink.play() } else { sink.pause() }
 
         let _ = scrobble_upstream.send_blocking(ScrobbleRequest::NowPlaying(queue_item.song.clone()));
 
@@ -156,241 +431,426 @@ fn load_audio(
     changed_audio
 }
 
+#[allow(clippy::too_many_arguments)]
 fn audio_thread(
-    sink: Sink, task_downstream: Receiver<AudioTask>,
+    sink: Sink, events: EventReceiver<AudioEvent>,
     queue_upstream: Sender<QueueFramework>,
     progress_upstream: Sender<ProgressUpdate>,
-    scrobble_upstream: Sender<ScrobbleRequest>
+    scrobble_upstream: Sender<ScrobbleRequest>,
+    visual_upstream: Sender<VisualUpdate>,
+    mut pipeline: Pipeline,
+    progress_interval: Duration,
+    autoplay: bool
 ) {
 
     let mut queue: Queue = Queue::new();
+    queue.autoplay = autoplay;
     let mut now_playing: Option<usize> = None;
-    let mut scrobble_applied = false;
-    let mut first_song = true;
+    let mut listen_tracker = ListenTracker::new();
+    let mut spectrum = Spectrum::new();
+    let mut persisted: Option<QueueSnapshot> = None;
+    let mut last_persist = Instant::now();
+    let mut last_progress = Instant::now();
+    let mut unsaved = false;
+    let mut volume = 1f32;
 
     loop {
-        sleep(Duration::from_millis(200));
+        let playing = now_playing.is_some() && !sink.is_paused();
+
+        // Sleep until something happens, or until progress, the queue or the sleep timer need attention
+        let now = Instant::now();
+        let wake = [
+            if playing { Some(last_progress + progress_interval) } else { None },
+            if unsaved { Some(last_persist + PERSIST_INTERVAL) } else { None },
+            queue.sleep_until.map(|deadline| match deadline.checked_sub(SLEEP_FADE) {
+                Some(fade_start) if fade_start > now => fade_start,
+                _ if playing => now + FADE_STEP,
+                _ => deadline
+            })
+        ].into_iter().flatten().min();
 
-        let _ = progress_upstream.send_blocking(match queue.songs.get(queue.position) {
-            Some(song) => ProgressUpdate::Seconds(
-                sink.get_pos().as_secs_f32(),
-                song.song.duration.as_secs_f32()
-            ),
-            None => ProgressUpdate::Nothing
-        });
+        let first = match wake {
+            Some(wake) => events.recv_timeout(wake.saturating_duration_since(now)),
+            None => events.recv().map_err(|_| RecvTimeoutError::Disconnected)
+        };
+
+        let first = match first {
+            Ok(event) => Some(event),
+            Err(RecvTimeoutError::Timeout) => None,
+            Err(RecvTimeoutError::Disconnected) => return
+        };
 
+        let handled_events = first.is_some();
         let mut should_audio_be_reloaded = false;
-        let mut do_not_skip = false;
+        let mut play_next = true;
 
-        while let Ok(task) = task_downstream.try_recv() {
-            let need_reload = match task {
-                AudioTask::Play => {
-                    sink.play();
-                    update_queue(&sink, &queue, &queue_upstream);
-                    false
-                }
-                AudioTask::Pause => {
-                    sink.pause();
-                    update_queue(&sink, &queue, &queue_upstream);
-                    false
-                }
-                AudioTask::TogglePlayback => {
-                    println!("PLAYBACK TOGGLED!");
-                    if sink.is_paused() { sink.play() } else { sink.pause() }
-                    update_queue(&sink, &queue, &queue_upstream);
-                    false
-                },
+        let drained = first.into_iter().chain(std::iter::from_fn(|| events.try_recv().ok()));
+        for event in drained.chain(std::iter::once(AudioEvent::SourceEnded)) {
+            let need_reload = match event {
+                AudioEvent::SourceEnded => {
+                    // Stale if the song was replaced before it finished, or already handled
+                    if !pipeline.take_ended() { continue }
 
-                AudioTask::SkipForward => {
-                    if queue.position < queue.songs.len() - 1 { queue.position += 1 }
-                    true
-                }
+                    if queue.stop_after_current {
+                        queue.stop_after_current = false;
+                        play_next = false;
+                    }
 
-                AudioTask::SkipBackward => {
-                    if queue.position > 0 { queue.position -= 1 }
+                    if !queue.repeat {
+                        queue.jump(match queue.position + 1 < queue.songs.len() {
+                            true => queue.position + 1,
+                            false => 0
+                        });
+                    }
                     true
                 }
-                
-                AudioTask::Push(song) => {
-                    if let Some(queue_item) = QueueItem::new(song) {
-                        queue.songs.push(queue_item);
+
+                AudioEvent::RadioPick(generation, song) => {
+                    // Dropped if another song started or the queue grew while it was being picked
+                    if generation == pipeline.generation && queue.wants_radio() {
+                        if let Some(queue_item) = QueueItem::new(song) {
+                            queue.songs.push(queue_item);
+                            update_queue(&sink, &queue, &queue_upstream);
+                        }
                     }
-                    update_queue(&sink, &queue, &queue_upstream);
                     false
                 }
-                AudioTask::Insert(song) => {
-                    if let Some(queue_item) = QueueItem::new(song) {
-                        queue.songs.insert(0, queue_item);
-                    }
 
-                    // Offsets all the other songs, thus account for this
-                    if queue.songs.len() != 1 {
-                        queue.position += 1;
+                AudioEvent::Task(task) => match task {
+                    AudioTask::Play => {
+                        sink.play();
+                        update_queue(&sink, &queue, &queue_upstream);
+                        false
                     }
-                    update_queue(&sink, &queue, &queue_upstream);
-                    false
-                },
-                AudioTask::Move(target) => {
-                    if queue.position == target {
-                        continue
+                    AudioTask::Pause => {
+                        sink.pause();
+                        update_queue(&sink, &queue, &queue_upstream);
+                        false
                     }
+                    AudioTask::TogglePlayback => {
+                        println!("PLAYBACK TOGGLED!");
+                        if sink.is_paused() { sink.play() } else { sink.pause() }
+                        update_queue(&sink, &queue, &queue_upstream);
+                        false
+                    },
 
-                    if target >= queue.songs.len() {
-                        continue
+                    AudioTask::SkipForward => {
+                        if queue.position + 1 < queue.songs.len() { queue.jump(queue.position + 1) }
+                        true
                     }
 
-                    queue.position = target;
-                    true
-                }
-                AudioTask::SetQueue(songs) => {
-                    sink.clear();
-                    queue.position = 0;
-                    queue.songs = songs.into_iter().filter_map(QueueItem::new).collect();
-                    sink.play();
-                    do_not_skip = true;
-                    true
-                }
-                AudioTask::RemoveSongById(song_id) => {
-                    let idx = match queue.songs.iter().enumerate().find_map(|(i, qi)|
-                        if qi.song.id == song_id { Some(i) } else { None }
-                    ) {
-                        Some(idx) => idx,
-                        None => continue
-                    };
-
-                    if idx < queue.position {
-                        queue.position -= 1;
+                    AudioTask::SkipBackward => {
+                        if queue.position > 0 { queue.jump(queue.position - 1) }
+                        true
+                    }
+                
+                    AudioTask::Push(song) => {
+                        if let Some(queue_item) = QueueItem::new(song) {
+                            let idx = queue.push_index();
+                            queue.songs.insert(idx, queue_item);
+                        }
+                        update_queue(&sink, &queue, &queue_upstream);
+                        false
                     }
+                    AudioTask::Insert(song) => {
+                        if let Some(queue_item) = QueueItem::new(song) {
+                            queue.insert_next(queue_item);
+                        }
+                        update_queue(&sink, &queue, &queue_upstream);
+                        false
+                    },
+                    AudioTask::Move(target) => {
+                        if queue.position == target {
+                            continue
+                        }
 
-                    queue.songs.remove(idx);
-                    true
-                }
-                AudioTask::RemoveSongByIdx(idx) => {
-                    if idx >= queue.songs.len() {
-                        continue
+                        if target >= queue.songs.len() {
+                            continue
+                        }
+
+                        queue.jump(target);
+                        true
                     }
+                    AudioTask::Seek(seconds) => {
+                        if now_playing.is_some() {
+                            let _ = sink.try_seek(Duration::from_secs_f32(seconds.max(0f32)));
+                        }
+                        false
+                    }
+                    AudioTask::SetLoop(section) => {
+                        pipeline.looper.set(section);
+                        queue.loop_section = section.filter(|(start, end)| end - start >= MIN_LOOP_LENGTH);
+                        if let Some((start, _)) = queue.loop_section {
+                            // Start practising from the top of the section straight away
+                            if now_playing.is_some() && pipeline.playhead.seconds() < start {
+                                let _ = sink.try_seek(Duration::from_secs_f32(start));
+                            }
+                        }
+                        update_queue(&sink, &queue, &queue_upstream);
+                        false
+                    }
+                    AudioTask::MoveItem(from, to) => {
+                        // Moving the current song keeps it playing, so there is nothing to reload
+                        if queue.move_item(from, to) {
+                            update_queue(&sink, &queue, &queue_upstream);
+                        }
+                        false
+                    }
+                    AudioTask::SetQueue(songs) => {
+                        sink.clear();
+                        queue.position = 0;
+                        queue.up_next = 0;
+                        queue.songs = songs.into_iter().filter_map(QueueItem::new).collect();
+                        sink.play();
+                        true
+                    }
+                    AudioTask::RemoveSongById(song_id) => {
+                        let idx = match queue.songs.iter().enumerate().find_map(|(i, qi)|
+                            if qi.song.id == song_id { Some(i) } else { None }
+                        ) {
+                            Some(idx) => idx,
+                            None => continue
+                        };
 
-                    if idx < queue.position {
-                        queue.position -= 1;
+                        queue.remove(idx);
+                        true
                     }
+                    AudioTask::RemoveSongByIdx(idx) => {
+                        if idx >= queue.songs.len() {
+                            continue
+                        }
 
-                    queue.songs.remove(idx);
-                    true
-                }
-                AudioTask::ToggleRepeat => {
-                    queue.repeat = !queue.repeat;
-                    update_queue(&sink, &queue, &queue_upstream);
-                    false
-                }
-                AudioTask::SetVolume(volume) => {
-                    sink.set_volume(volume);
-                    update_queue(&sink, &queue, &queue_upstream);
-                    false
-                }
-                AudioTask::ClearQueue => {
-                    queue.songs.clear();
-                    queue.position = 0;
-                    first_song = true;
-                    true
+                        queue.remove(idx);
+                        true
+                    }
+                    AudioTask::ToggleRepeat => {
+                        queue.repeat = !queue.repeat;
+                        update_queue(&sink, &queue, &queue_upstream);
+                        false
+                    }
+                    AudioTask::SetShuffle(shuffle) => {
+                        if shuffle && !queue.shuffle { queue.shuffle_upcoming(); }
+                        queue.shuffle = shuffle;
+                        update_queue(&sink, &queue, &queue_upstream);
+                        false
+                    }
+                    AudioTask::Restore(snapshot) => {
+                        queue.songs = snapshot.songs.into_iter().filter_map(QueueItem::new).collect();
+                        queue.position = snapshot.position.min(queue.songs.len().saturating_sub(1));
+                        queue.up_next = 0;
+                        queue.repeat = snapshot.repeat;
+                        queue.shuffle = snapshot.shuffle;
+
+                        // Come back paused where we left off rather than blasting music on launch
+                        now_playing = load_audio(
+                            &sink, &mut queue, &queue_upstream, &scrobble_upstream, &mut pipeline, false
+                        );
+                        if now_playing.is_some() && snapshot.offset > 0f32 {
+                            let _ = sink.try_seek(Duration::from_secs_f32(snapshot.offset));
+                        }
+                        update_queue(&sink, &queue, &queue_upstream);
+                        false
+                    }
+                    AudioTask::SetVolume(new_volume) => {
+                        volume = new_volume;
+                        sink.set_volume(volume);
+                        update_queue(&sink, &queue, &queue_upstream);
+                        false
+                    }
+                    AudioTask::SetSleepTimer(duration) => {
+                        queue.sleep_until = duration.map(|duration| Instant::now() + duration);
+                        sink.set_volume(volume);
+                        update_queue(&sink, &queue, &queue_upstream);
+                        false
+                    }
+                    AudioTask::SetStopAfterCurrent(stop_after_current) => {
+                        queue.stop_after_current = stop_after_current;
+                        update_queue(&sink, &queue, &queue_upstream);
+                        false
+                    }
+                    AudioTask::SetAutoplay(autoplay) => {
+                        queue.autoplay = autoplay;
+                        update_queue(&sink, &queue, &queue_upstream);
+                        false
+                    }
+                    AudioTask::SetNormalisation(mode) => {
+                        // Takes effect from the next song so the current one doesn't jump in volume
+                        pipeline.normalisation = mode;
+                        false
+                    }
+                    AudioTask::SetEqualizer(gains) => {
+                        pipeline.equalizer.set(gains);
+                        false
+                    }
+                    AudioTask::SetSpeed(speed) => {
+                        pipeline.tempo.set_speed(speed);
+                        false
+                    }
+                    AudioTask::SetPreservePitch(preserve_pitch) => {
+                        pipeline.tempo.set_preserve_pitch(preserve_pitch);
+                        false
+                    }
+                    AudioTask::ClearQueue => {
+                        queue.songs.clear();
+                        queue.position = 0;
+                        queue.up_next = 0;
+                        true
+                    }
+                    AudioTask::EndThread => {
+                        let snapshot = queue.snapshot(pipeline.playhead.seconds().floor());
+                        DatabaseInterface::blocking_save_queue(pipeline.database.clone(), &snapshot);
+                        listen_tracker.update(pipeline.playhead.seconds());
+                        if let Some(listen) = listen_tracker.finish() {
+                            DatabaseInterface::blocking_insert_listen(pipeline.database.clone(), &listen);
+                        }
+                        return
+                    }
                 }
-                AudioTask::EndThread => return
             };
 
             if need_reload { should_audio_be_reloaded = true; }
         }
 
-        if sink.empty() && !queue.songs.is_empty() {
-            if queue.position < queue.songs.len() - 1 && !queue.repeat && !do_not_skip {
-                if first_song {
-                    first_song = false;
-                } else {
-                    queue.position += 1;
-                }
-            } else if queue.position == queue.songs.len() - 1 && !queue.repeat {
+        if let Some(song) = queue.songs.get(queue.position) {
+            // Nothing loaded yet, or the playing song was removed or replaced
+            if now_playing != Some(song.song.id) {
+                should_audio_be_reloaded = true;
+            }
+        }
+
+        if should_audio_be_reloaded {
+            now_playing = load_audio(
+                &sink, &mut queue, &queue_upstream, &scrobble_upstream, &mut pipeline, play_next
+            );
+            if let Some(song_id) = now_playing {
+                pipeline.radio.played(song_id);
+            }
+            if now_playing.is_none() {
                 queue.position = 0;
+                queue.up_next = 0;
+                sink.clear();
             }
-            should_audio_be_reloaded = true;
         }
 
-        if let Some(song) = queue.songs.get(queue.position) {
-            if let Some(playing) = now_playing {
-                if song.song.id != playing {
-                    should_audio_be_reloaded = true;
-                }
+        // Out of songs soon, so find something similar from the library while this one plays
+        if now_playing.is_some() && queue.wants_radio() {
+            pipeline.radio.request(pipeline.generation, queue.songs.iter().map(|qi| &qi.song));
+        }
+
+        // Song time rather than sink time, so listening at any speed counts the same
+        if should_audio_be_reloaded || listen_tracker.song_id() != now_playing {
+            let song = queue.songs.get(queue.position)
+                .filter(|qi| Some(qi.song.id) == now_playing)
+                .map(|qi| qi.song.clone());
+            if let Some(listen) = listen_tracker.start(song, pipeline.playhead.seconds()) {
+                DatabaseInterface::insert_listen(pipeline.database.clone(), &listen);
             }
+        } else {
+            listen_tracker.update(pipeline.playhead.seconds());
+        }
 
-            let seconds_in = sink.get_pos().as_secs_f32();
-            let total_seconds = song.song.duration.as_secs_f32();
-            let ratio = seconds_in / total_seconds;
+        if let Some(song) = listen_tracker.scrobble() {
+            let _ = scrobble_upstream.send_blocking(ScrobbleRequest::Scrobble(song.clone()));
+        }
 
-            if !scrobble_applied && ratio > 0.2f32 {
-                scrobble_applied = true;
-                let _ = scrobble_upstream.send_blocking(ScrobbleRequest::Scrobble(song.song.clone()));
+        if let Some(deadline) = queue.sleep_until {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            if remaining.is_zero() {
+                queue.sleep_until = None;
+                sink.pause();
+                sink.set_volume(volume);
+                update_queue(&sink, &queue, &queue_upstream);
+            } else {
+                sink.set_volume(volume * (remaining.as_secs_f32() / SLEEP_FADE.as_secs_f32()).min(1f32));
             }
         }
-        
-        if should_audio_be_reloaded {
-            now_playing = load_audio(&sink, &mut queue, &queue_upstream, &scrobble_upstream);
-            scrobble_applied = false;
-            if now_playing.is_none() {
-                queue.position = 0;
-                sink.clear();
+
+        let playing = now_playing.is_some() && !sink.is_paused();
+        if handled_events || (playing && last_progress.elapsed() >= progress_interval) {
+            last_progress = Instant::now();
+            let _ = progress_upstream.send_blocking(match (now_playing, queue.songs.get(queue.position)) {
+                (Some(_), Some(song)) => ProgressUpdate::Seconds(
+                    pipeline.playhead.seconds(),
+                    song.song.duration.as_secs_f32()
+                ),
+                _ => ProgressUpdate::Nothing
+            });
+
+            // Dropped rather than queued if the interface falls behind, only the latest frame matters
+            if playing {
+                let _ = visual_upstream.try_send(spectrum.update(&pipeline.tap));
+            }
+        }
+
+        unsaved = unsaved || handled_events || playing;
+        if unsaved && last_persist.elapsed() >= PERSIST_INTERVAL {
+            last_persist = Instant::now();
+            unsaved = false;
+            let snapshot = queue.snapshot(pipeline.playhead.seconds().floor());
+            if persisted.as_ref() != Some(&snapshot) {
+                let songs_changed = persisted.as_ref().is_none_or(|persisted| persisted.songs != snapshot.songs);
+                DatabaseInterface::save_queue(pipeline.database.clone(), &snapshot, songs_changed);
+                persisted = Some(snapshot);
             }
         }
     }
 }
 
 pub struct AudioPlayer {
-    _stream: OutputStream,
-    _handle: OutputStreamHandle,
-    _thread_handle: JoinHandle<()>,
-    task_upstream: Sender<AudioTask>,
+    _output: Output,
+    thread_handle: JoinHandle<()>,
+    task_upstream: SyncSender<AudioEvent>,
 }
 
 type AudioChannels = (
     AudioPlayer,
     Receiver<QueueFramework>,
     Receiver<ProgressUpdate>,
-    Receiver<ScrobbleRequest>
+    Receiver<ScrobbleRequest>,
+    Receiver<VisualUpdate>
 );
 
 impl AudioPlayer {
-    pub fn new() -> Result<AudioChannels, ResonateError> {
-        let (task_upstream, task_downstream) = bounded::<AudioTask>(256);
+    pub fn new(database: DataLink, music_path: PathBuf, settings: &Settings) -> Result<AudioChannels, ResonateError> {
+        let (task_upstream, task_downstream) = sync_channel::<AudioEvent>(256);
         let (queue_upstream, queue_downstream) = bounded::<QueueFramework>(256);
         let (progress_upstream, progress_downstream) = bounded::<ProgressUpdate>(256);
         let (scrobble_upstream, scrobble_downstream) = bounded::<ScrobbleRequest>(256);
+        let (visual_upstream, visual_downstream) = bounded::<VisualUpdate>(4);
 
-        let (_stream, handle) = match OutputStream::try_default() {
-            Ok(data) => data,
-            Err(_) => return Err(ResonateError::AudioStreamError)
-        };
-
-        let sink = match Sink::try_new(&handle) {
-            Ok(sink) => sink,
-            Err(_) => return Err(ResonateError::AudioStreamError)
-        };
+        let (_output, sink) = Output::open(OutputBackend::resolve(&settings.output))?;
 
-        let _thread_handle = spawn(
+        let pipeline = Pipeline::new(database, music_path, settings, task_upstream.clone());
+        let progress_interval = Duration::from_millis(settings.progress_interval);
+        let autoplay = settings.autoplay;
+        let thread_handle = spawn(
             move || audio_thread(
-                sink, task_downstream, queue_upstream, progress_upstream, scrobble_upstream
+                sink, task_downstream, queue_upstream, progress_upstream, scrobble_upstream, visual_upstream,
+                pipeline, progress_interval, autoplay
             )
         );
 
         Ok((AudioPlayer {
-            _stream,
-            _handle: handle,
-            _thread_handle,
+            _output,
+            thread_handle,
             task_upstream,
         },
             queue_downstream,
             progress_downstream,
-            scrobble_downstream
+            scrobble_downstream,
+            visual_downstream
         ))
     }
 
+    /// Stop the audio thread, waiting for it to save the queue
+    pub fn shutdown(self) {
+        if self.send_task(AudioTask::EndThread).is_ok() {
+            let _ = self.thread_handle.join();
+        }
+    }
+
     pub fn send_task(&self, task: AudioTask) -> Result<(), ()> {
-        match self.task_upstream.send_blocking(task) {
+        match self.task_upstream.send(AudioEvent::Task(task)) {
             Ok(_) => Ok(()),
             Err(e) => {
                 println!("[AUDIO] Error sending task: {e:?}");
@@ -399,3 +859,167 @@ impl AudioPlayer {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn item(id: usize) -> QueueItem {
+        QueueItem { song: Song {
+            id,
+            yt_id: id.to_string(),
+            title: String::new(),
+            artist: String::new(),
+            album: None,
+            duration: Duration::ZERO,
+            music_path: Some(format!("{id}.m4a").into())
+        } }
+    }
+
+    /// Songs with ids `0..len`, the current one at `position`
+    fn numbered(len: usize, position: usize) -> Queue {
+        let mut queue = Queue::new();
+        queue.songs = (0..len).map(item).collect();
+        queue.position = position;
+        queue
+    }
+
+    fn ids(queue: &Queue) -> Vec<usize> {
+        queue.songs.iter().map(|qi| qi.song.id).collect()
+    }
+
+    fn current(queue: &Queue) -> usize {
+        queue.songs[queue.position].song.id
+    }
+
+    #[test]
+    fn insert_next_keeps_the_order_it_was_asked_in() {
+        let mut queue = numbered(4, 0);
+        queue.insert_next(item(10));
+        queue.insert_next(item(11));
+        assert_eq!(ids(&queue), [0, 10, 11, 1, 2, 3]);
+        assert_eq!(queue.up_next, 2);
+    }
+
+    #[test]
+    fn jump_inside_up_next() {
+        let mut queue = numbered(4, 0);
+        queue.insert_next(item(10));
+        queue.insert_next(item(11));
+
+        queue.jump(1);
+        assert_eq!(ids(&queue), [0, 10, 11, 1, 2, 3]);
+        assert_eq!(current(&queue), 10);
+        assert_eq!(queue.up_next, 1);
+    }
+
+    #[test]
+    fn jump_forwards_past_up_next_keeps_it_queued() {
+        let mut queue = numbered(4, 0);
+        queue.insert_next(item(10));
+        queue.insert_next(item(11));
+
+        queue.jump(4);
+        assert_eq!(ids(&queue), [0, 1, 2, 10, 11, 3]);
+        assert_eq!(current(&queue), 2);
+        assert_eq!(queue.up_next, 2);
+    }
+
+    #[test]
+    fn jump_backwards_keeps_up_next_queued() {
+        let mut queue = numbered(4, 2);
+        queue.insert_next(item(10));
+
+        queue.jump(0);
+        assert_eq!(ids(&queue), [0, 10, 1, 2, 3]);
+        assert_eq!(current(&queue), 0);
+        assert_eq!(queue.up_next, 1);
+    }
+
+    #[test]
+    fn remove_before_current() {
+        let mut queue = numbered(3, 2);
+        queue.remove(0);
+        assert_eq!(ids(&queue), [1, 2]);
+        assert_eq!(current(&queue), 2);
+    }
+
+    #[test]
+    fn remove_current_plays_the_next_song() {
+        let mut queue = numbered(3, 1);
+        queue.remove(1);
+        assert_eq!(current(&queue), 2);
+    }
+
+    #[test]
+    fn remove_current_hands_over_to_up_next() {
+        let mut queue = numbered(2, 0);
+        queue.insert_next(item(10));
+
+        queue.remove(0);
+        assert_eq!(ids(&queue), [10, 1]);
+        assert_eq!(current(&queue), 10);
+        assert_eq!(queue.up_next, 0);
+    }
+
+    #[test]
+    fn remove_current_last_song_stays_in_bounds() {
+        let mut queue = numbered(3, 2);
+        queue.remove(2);
+        assert_eq!(queue.position, 1);
+
+        let mut queue = numbered(1, 0);
+        queue.remove(0);
+        assert_eq!(queue.position, 0);
+        assert!(queue.songs.is_empty());
+    }
+
+    #[test]
+    fn move_into_up_next() {
+        let mut queue = numbered(3, 0);
+        queue.insert_next(item(10));
+        queue.insert_next(item(11));
+
+        assert!(queue.move_item(4, 1));
+        assert_eq!(ids(&queue), [0, 2, 10, 11, 1]);
+        assert_eq!(queue.up_next, 3);
+    }
+
+    #[test]
+    fn move_out_of_up_next() {
+        let mut queue = numbered(3, 0);
+        queue.insert_next(item(10));
+        queue.insert_next(item(11));
+
+        assert!(queue.move_item(1, 4));
+        assert_eq!(ids(&queue), [0, 11, 1, 2, 10]);
+        assert_eq!(queue.up_next, 1);
+    }
+
+    #[test]
+    fn move_to_the_end_of_up_next() {
+        let mut queue = numbered(3, 0);
+        queue.insert_next(item(10));
+        queue.insert_next(item(11));
+
+        assert!(queue.move_item(1, 2));
+        assert_eq!(ids(&queue), [0, 11, 10, 1, 2]);
+        assert_eq!(queue.up_next, 2);
+    }
+
+    #[test]
+    fn move_current_follows_it() {
+        let mut queue = numbered(4, 1);
+        assert!(queue.move_item(1, 3));
+        assert_eq!(ids(&queue), [0, 2, 3, 1]);
+        assert_eq!(current(&queue), 1);
+    }
+
+    #[test]
+    fn move_before_current_shifts_position() {
+        let mut queue = numbered(4, 1);
+        assert!(queue.move_item(3, 0));
+        assert_eq!(ids(&queue), [3, 0, 1, 2]);
+        assert_eq!(current(&queue), 1);
+    }
+}
@@ -5,19 +5,81 @@ use crate::backend::database_manager::DatabaseParam;
 use crate::backend::database_manager::DatabaseParams;
 use crate::backend::sql::*;
 use crate::backend::music::Song;
+use crate::backend::music::NO_ALBUM;
+use crate::backend::music::PLAYABLE_EXTENSIONS;
 use crate::backend::database_manager::ItemStream;
 use crate::backend::music::Playlist;
 use crate::backend::settings::Secret;
+use crate::backend::loudness::Loudness;
+use crate::backend::equalizer::EqualizerPreset;
+use crate::backend::audio::QueueSnapshot;
+use crate::backend::listening::Listen;
+use crate::backend::looper::Section;
+use crate::backend::downloads::DownloadEntry;
+use crate::backend::downloads::DownloadState;
 
 pub struct DatabaseInterface;
 impl DatabaseInterface {
     
     /// Create the tables
-    pub async fn create_tables(database: DataLink) {
+    pub async fn create_tables(database: DataLink, music_path: std::path::PathBuf) {
         let _ = database.execute(CREATE_SONG_TABLE, DatabaseParams::empty());
+        Self::add_song_extensions(database.clone(), music_path).await;
         let _ = database.execute(CREATE_PLAYLIST_TABLE, DatabaseParams::empty());
         let _ = database.execute(CREATE_PLAYLIST_ENTRIES_TABLE, DatabaseParams::empty());
         let _ = database.execute(CREATE_SECRETS_TABLE, DatabaseParams::empty());
+        let _ = database.execute(CREATE_LOUDNESS_TABLE, DatabaseParams::empty());
+        let _ = database.execute(CREATE_EQUALIZER_PRESETS_TABLE, DatabaseParams::empty());
+        let _ = database.execute(CREATE_QUEUE_TABLE, DatabaseParams::empty());
+        let _ = database.execute(CREATE_QUEUE_ENTRIES_TABLE, DatabaseParams::empty());
+        let _ = database.execute(CREATE_HISTORY_TABLE, DatabaseParams::empty());
+        let _ = database.execute(CREATE_SECTIONS_TABLE, DatabaseParams::empty());
+        let _ = database.execute(CREATE_DOWNLOADS_TABLE, DatabaseParams::empty());
+    }
+
+    /// Add the extension column to a library made before it existed, and fill it in from the music folder
+    async fn add_song_extensions(database: DataLink, music_path: std::path::PathBuf) {
+        match database.query_map(COUNT_SONG_EXTENSION_COLUMN, DatabaseParams::empty()).await {
+            Ok(rows) if rows.first().and_then(|row| row.first()).is_some_and(|count| count.usize() == 0) => {},
+            _ => return
+        }
+
+        let _ = database.execute(ADD_SONG_EXTENSION_COLUMN, DatabaseParams::empty());
+
+        let mut files: Vec<(usize, String, String)> = match std::fs::read_dir(&music_path) {
+            Ok(entries) => entries.flatten().filter_map(|entry| {
+                let path = entry.path();
+                let yt_id = path.file_stem()?.to_string_lossy().to_string();
+                let extension = path.extension()?.to_string_lossy().to_lowercase();
+                let rank = PLAYABLE_EXTENSIONS.iter().position(|playable| *playable == extension)?;
+
+                // Leftovers like `{id}.temp.m4a` aren't songs
+                match yt_id.contains('.') {
+                    true => None,
+                    false => Some((rank, yt_id, extension))
+                }
+            }).collect(),
+            Err(_) => return
+        };
+
+        // Where a song was saved in more than one format, the one looked for first is written last
+        files.sort_by_key(|file| std::cmp::Reverse(file.0));
+        for (_, yt_id, extension) in files {
+            let _ = database.execute(UPDATE_SONG_EXTENSION_BY_YOUTUBE_ID, DatabaseParams::new(vec![
+                DatabaseParam::String(extension), DatabaseParam::String(yt_id)
+            ]));
+        }
+    }
+
+    /// Store the extension a song was downloaded with, so it can be found without looking
+    pub fn update_song_extension(database: DataLink, song: &Song) {
+        let extension = match song.extension() {
+            Some(extension) => DatabaseParam::String(extension),
+            None => DatabaseParam::Null
+        };
+        let _ = database.execute(UPDATE_SONG_EXTENSION, DatabaseParams::new(vec![
+            extension, DatabaseParam::Usize(song.id)
+        ]));
     }
 
     /// Remove song from playlist given song id and playlist id
@@ -37,21 +99,32 @@ impl DatabaseInterface {
     pub async fn construct_song(
         row: Vec<DatabaseParam>, music_path: std::path::PathBuf
     ) -> Option<Song> {
-        if row.len() != 6 {
+        tokio::task::spawn_blocking(move || Self::blocking_construct_song(row, music_path)).await.ok().flatten()
+    }
+
+    /// Make song from a single row on the calling thread
+    pub fn blocking_construct_song(
+        row: Vec<DatabaseParam>, music_path: std::path::PathBuf
+    ) -> Option<Song> {
+        if row.len() != 7 {
             return None;
         }
 
-        tokio::task::spawn_blocking(move || 
-            Song::new(
-                row[0].usize(),
-                row[1].string(),
-                row[2].string(),
-                row[3].string(),
-                Some(row[4].string()),
-                std::time::Duration::from_secs(row[5].usize() as u64),
-                music_path
-            )
-        ).await.ok()
+        let yt_id = row[1].string();
+        let file = match &row[6] {
+            DatabaseParam::String(extension) => Some(Song::file_path(&music_path, &yt_id, extension)),
+            _ => None
+        };
+
+        Some(Song::new(
+            row[0].usize(),
+            yt_id,
+            row[2].string(),
+            row[3].string(),
+            Some(row[4].string()),
+            std::time::Duration::from_secs(row[5].usize() as u64),
+            file
+        ))
     }
 
     pub fn construct_playlist(
@@ -89,7 +162,7 @@ impl DatabaseInterface {
             DatabaseParam::String(song.yt_id),
             DatabaseParam::String(song.title),
             DatabaseParam::String(song.artist),
-            DatabaseParam::String(song.album.take().unwrap_or(String::from("none"))),
+            DatabaseParam::String(song.album.take().unwrap_or(String::from(NO_ALBUM))),
             DatabaseParam::Usize(song.duration.as_secs() as usize)
         ])).await
     }
@@ -160,6 +233,19 @@ impl DatabaseInterface {
         Self::construct_songs(rows, music_path).await.pop()
     }
 
+    /// Look a song up by its YouTube ID
+    pub async fn select_song_by_youtube_id(
+        database: DataLink, yt_id: String, music_path: std::path::PathBuf
+    ) -> Option<Song> {
+        let rows = match database.query_map(
+            SELECT_SONG_BY_YOUTUBE_ID, DatabaseParams::single(DatabaseParam::String(yt_id))
+        ).await {
+            Ok(rows) => rows,
+            Err(_) => return None
+        };
+        Self::construct_songs(rows, music_path).await.pop()
+    }
+
     /// Batch load secrets
     pub async fn select_multiple_secrets(
         database: DataLink, secrets: Vec<String>
@@ -236,7 +322,7 @@ impl DatabaseInterface {
             DatabaseParam::String(song.yt_id),
             DatabaseParam::String(song.title),
             DatabaseParam::String(song.artist),
-            DatabaseParam::String(song.album.take().unwrap_or(String::from("none"))),
+            DatabaseParam::String(song.album.take().unwrap_or(String::from(NO_ALBUM))),
             DatabaseParam::Usize(song.duration.as_secs() as usize)
         ])).recv_blocking() {
             Ok(res) => match res {
@@ -246,4 +332,278 @@ impl DatabaseInterface {
             Err(_) => Err(())
         }
     }
+
+    /// Store the measured loudness of a song, replacing any previous measurement
+    pub fn insert_loudness(database: DataLink, song_id: usize, loudness: Loudness) {
+        let _ = database.execute(INSERT_LOUDNESS, DatabaseParams::new(vec![
+            DatabaseParam::Usize(song_id),
+            DatabaseParam::F64(loudness.integrated),
+            DatabaseParam::F64(loudness.peak)
+        ]));
+    }
+
+    fn construct_loudness(row: Vec<DatabaseParam>) -> Option<Loudness> {
+        if row.len() != 3 {
+            None
+        } else {
+            Some(Loudness { integrated: row[1].f64(), peak: row[2].f64() })
+        }
+    }
+
+    pub fn blocking_select_loudness(database: DataLink, song_id: usize) -> Option<Loudness> {
+        database.blocking_query_map(
+            SELECT_LOUDNESS_BY_SONG_ID, DatabaseParams::single(DatabaseParam::Usize(song_id))
+        ).ok()?.into_iter().filter_map(Self::construct_loudness).next()
+    }
+
+    /// Every loudness measurement for songs sharing an album
+    pub fn blocking_select_album_loudness(database: DataLink, album: String) -> Vec<Loudness> {
+        match database.blocking_query_map(
+            SELECT_ALBUM_LOUDNESS, DatabaseParams::single(DatabaseParam::String(album))
+        ) {
+            Ok(rows) => rows.into_iter().filter_map(Self::construct_loudness).collect(),
+            Err(_) => Vec::new()
+        }
+    }
+
+    /// Downloaded songs that have not had their loudness measured
+    pub fn blocking_select_songs_without_loudness(
+        database: DataLink, music_path: std::path::PathBuf
+    ) -> Vec<Song> {
+        match database.blocking_query_map(SELECT_SONGS_WITHOUT_LOUDNESS, DatabaseParams::empty()) {
+            Ok(rows) => rows.into_iter()
+                .filter_map(|row| Self::blocking_construct_song(row, music_path.clone()))
+                .filter(|song| song.music_path.is_some())
+                .collect(),
+            Err(_) => Vec::new()
+        }
+    }
+
+    /// Every song in the library, on the calling thread
+    pub fn blocking_select_all_songs(database: DataLink, music_path: std::path::PathBuf) -> Vec<Song> {
+        match database.blocking_query_map(SELECT_ALL_SONGS, DatabaseParams::empty()) {
+            Ok(rows) => rows.into_iter()
+                .filter_map(|row| Self::blocking_construct_song(row, music_path.clone()))
+                .collect(),
+            Err(_) => Vec::new()
+        }
+    }
+
+    /// Every (playlist id, song id) pair
+    pub fn blocking_select_all_entries(database: DataLink) -> Vec<(usize, usize)> {
+        match database.blocking_query_map(SELECT_ALL_ENTRIES, DatabaseParams::empty()) {
+            Ok(rows) => rows.into_iter()
+                .filter(|row| row.len() == 2)
+                .map(|row| (row[0].usize(), row[1].usize()))
+                .collect(),
+            Err(_) => Vec::new()
+        }
+    }
+
+    /// Save a user equalizer preset, returning it with its new ID
+    pub async fn insert_equalizer_preset(
+        database: DataLink, mut preset: EqualizerPreset
+    ) -> Option<EqualizerPreset> {
+        preset.id = Some(database.insert(INSERT_EQUALIZER_PRESET, DatabaseParams::new(vec![
+            DatabaseParam::String(preset.name.clone()),
+            DatabaseParam::String(EqualizerPreset::serialise_gains(&preset.gains))
+        ])).await?);
+        Some(preset)
+    }
+
+    /// Every equalizer preset the user has saved
+    pub async fn select_all_equalizer_presets(database: DataLink) -> Vec<EqualizerPreset> {
+        match database.query_map(SELECT_ALL_EQUALIZER_PRESETS, DatabaseParams::empty()).await {
+            Ok(rows) => rows.into_iter().filter_map(|row| {
+                if row.len() != 3 {
+                    None
+                } else {
+                    Some(EqualizerPreset {
+                        id: Some(row[0].usize()),
+                        name: row[1].string(),
+                        gains: EqualizerPreset::parse_gains(&row[2].string())?
+                    })
+                }
+            }).collect(),
+            Err(_) => Vec::new()
+        }
+    }
+
+    /// Delete a user equalizer preset by id
+    pub fn delete_equalizer_preset(database: DataLink, preset_id: usize) {
+        let _ = database.execute(REMOVE_EQUALIZER_PRESET, DatabaseParams::single(DatabaseParam::Usize(preset_id)));
+    }
+
+    fn queue_params(snapshot: &QueueSnapshot) -> DatabaseParams {
+        DatabaseParams::new(vec![
+            DatabaseParam::Usize(snapshot.position),
+            DatabaseParam::Usize(snapshot.repeat as usize),
+            DatabaseParam::Usize(snapshot.shuffle as usize),
+            DatabaseParam::F64(snapshot.offset as f64)
+        ])
+    }
+
+    fn queue_entries_params(snapshot: &QueueSnapshot) -> DatabaseParams {
+        let ids = snapshot.songs.iter().map(|song| song.id.to_string()).collect::<Vec<String>>().join(",");
+        DatabaseParams::single(DatabaseParam::String(format!("[{ids}]")))
+    }
+
+    /// Remember the queue so it can be restored next launch.
+    /// The songs are only rewritten when they have changed, not every time the position moves.
+    pub fn save_queue(database: DataLink, snapshot: &QueueSnapshot, songs_changed: bool) {
+        if songs_changed {
+            let _ = database.execute(REMOVE_ALL_QUEUE_ENTRIES, DatabaseParams::empty());
+            let _ = database.execute(INSERT_QUEUE_ENTRIES, Self::queue_entries_params(snapshot));
+        }
+        let _ = database.execute(INSERT_QUEUE, Self::queue_params(snapshot));
+    }
+
+    /// Same as `save_queue`, but only returns once the write has happened. Used when quitting.
+    pub fn blocking_save_queue(database: DataLink, snapshot: &QueueSnapshot) {
+        let _ = database.blocking_execute_and_wait(REMOVE_ALL_QUEUE_ENTRIES, DatabaseParams::empty());
+        let _ = database.blocking_execute_and_wait(INSERT_QUEUE_ENTRIES, Self::queue_entries_params(snapshot));
+        let _ = database.blocking_execute_and_wait(INSERT_QUEUE, Self::queue_params(snapshot));
+    }
+
+    /// Load the saved queue. Songs that have since been deleted or not downloaded are skipped.
+    pub async fn select_queue(database: DataLink, music_path: std::path::PathBuf) -> Option<QueueSnapshot> {
+        let row = database.query_map(SELECT_QUEUE, DatabaseParams::empty()).await.ok()?.pop()?;
+        if row.len() != 5 { return None; }
+
+        let position = row[1].usize();
+        let mut songs: Vec<Song> = Vec::new();
+        let mut new_position = 0;
+        let mut current_survived = false;
+
+        for mut entry in database.query_map(SELECT_QUEUE_SONGS, DatabaseParams::empty()).await.ok()? {
+            if entry.len() != 8 { continue; }
+            let song_row = entry.split_off(1);
+
+            let song = match Self::blocking_construct_song(song_row, music_path.clone()) {
+                Some(song) if song.music_path.is_some() => song,
+                _ => continue
+            };
+
+            let idx = entry[0].usize();
+            if idx < position { new_position += 1; }
+            if idx == position { current_survived = true; }
+            songs.push(song);
+        }
+
+        Some(QueueSnapshot {
+            position: new_position.min(songs.len().saturating_sub(1)),
+            songs,
+            repeat: row[2].usize() != 0,
+            shuffle: row[3].usize() != 0,
+            // The offset only means anything if it is still the same song
+            offset: if current_survived { row[4].f64() as f32 } else { 0f32 }
+        })
+    }
+
+    fn listen_params(listen: &Listen) -> DatabaseParams {
+        DatabaseParams::new(vec![
+            DatabaseParam::Usize(listen.song_id),
+            DatabaseParam::Usize(listen.started_at as usize),
+            DatabaseParam::F64(listen.listened as f64),
+            DatabaseParam::Usize(listen.scrobbled as usize)
+        ])
+    }
+
+    /// Add a listen to the play history
+    pub fn insert_listen(database: DataLink, listen: &Listen) {
+        let _ = database.execute(INSERT_HISTORY, Self::listen_params(listen));
+    }
+
+    /// Add a listen to the play history, waiting for it to be written
+    pub fn blocking_insert_listen(database: DataLink, listen: &Listen) {
+        let _ = database.blocking_execute_and_wait(INSERT_HISTORY, Self::listen_params(listen));
+    }
+
+    /// Save a named loop section, returning it with its new ID
+    pub async fn insert_section(database: DataLink, mut section: Section) -> Option<Section> {
+        section.id = Some(database.insert(INSERT_SECTION, DatabaseParams::new(vec![
+            DatabaseParam::Usize(section.song_id),
+            DatabaseParam::String(section.name.clone()),
+            DatabaseParam::F64(section.start as f64),
+            DatabaseParam::F64(section.end as f64)
+        ])).await?);
+        Some(section)
+    }
+
+    /// Every loop section saved for a song, in the order they appear
+    pub async fn select_sections(database: DataLink, song_id: usize) -> Vec<Section> {
+        match database.query_map(
+            SELECT_SECTIONS_BY_SONG_ID, DatabaseParams::single(DatabaseParam::Usize(song_id))
+        ).await {
+            Ok(rows) => rows.into_iter().filter_map(|row| {
+                if row.len() != 5 {
+                    None
+                } else {
+                    Some(Section {
+                        id: Some(row[0].usize()),
+                        song_id: row[1].usize(),
+                        name: row[2].string(),
+                        start: row[3].f64() as f32,
+                        end: row[4].f64() as f32
+                    })
+                }
+            }).collect(),
+            Err(_) => Vec::new()
+        }
+    }
+
+    pub fn delete_section(database: DataLink, section_id: usize) {
+        let _ = database.execute(REMOVE_SECTION, DatabaseParams::single(DatabaseParam::Usize(section_id)));
+    }
+
+    /// Add a song to the back of the download queue for its priority
+    pub fn insert_download(database: DataLink, song_id: usize, state: DownloadState, priority: usize) {
+        let (state, error) = state.to_row();
+        let _ = database.execute(INSERT_DOWNLOAD, DatabaseParams::new(vec![
+            DatabaseParam::Usize(song_id),
+            DatabaseParam::Usize(state),
+            DatabaseParam::Usize(priority),
+            DatabaseParam::Usize(error)
+        ]));
+    }
+
+    pub fn update_download_state(database: DataLink, song_id: usize, state: DownloadState) {
+        let (state, error) = state.to_row();
+        let _ = database.execute(UPDATE_DOWNLOAD_STATE, DatabaseParams::new(vec![
+            DatabaseParam::Usize(state),
+            DatabaseParam::Usize(error),
+            DatabaseParam::Usize(song_id)
+        ]));
+    }
+
+    pub fn remove_download(database: DataLink, song_id: usize) {
+        let _ = database.execute(REMOVE_DOWNLOAD, DatabaseParams::single(DatabaseParam::Usize(song_id)));
+    }
+
+    pub fn remove_downloads_by_state(database: DataLink, state: DownloadState) {
+        let _ = database.execute(REMOVE_DOWNLOADS_BY_STATE, DatabaseParams::single(DatabaseParam::Usize(state.to_row().0)));
+    }
+
+    /// The saved download queue in the order it should run
+    pub async fn select_downloads(database: DataLink, music_path: std::path::PathBuf) -> Vec<DownloadEntry> {
+        let rows = match database.query_map(SELECT_DOWNLOADS, DatabaseParams::empty()).await {
+            Ok(rows) => rows,
+            Err(_) => return Vec::new()
+        };
+
+        let mut entries = Vec::new();
+        for mut row in rows {
+            if row.len() != 10 { continue; }
+            let song_row = row.split_off(3);
+            if let Some(song) = Self::construct_song(song_row, music_path.clone()).await {
+                entries.push(DownloadEntry {
+                    song,
+                    state: DownloadState::from_row(row[0].usize(), row[2].usize()),
+                    priority: row[1].usize(),
+                    size: None
+                });
+            }
+        }
+        entries
+    }
 }
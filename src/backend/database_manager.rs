@@ -60,6 +60,14 @@ impl DatabaseParam {
         if let Self::String(v) = self { return v.clone(); }
         panic!("Attempted to get a STRING from a non-string value");
     }
+
+    pub fn f64(&self) -> f64 {
+        match self {
+            Self::F64(v) => *v,
+            Self::Usize(v) => *v as f64,
+            _ => panic!("Attempted to get a F64 from a non-numeric value")
+        }
+    }
 }
 
 pub struct DatabaseParams {
@@ -105,6 +113,13 @@ impl DataLink {
         }
     }
 
+    /// Execute and block the calling thread until the database has finished
+    pub fn blocking_execute_and_wait(&self, query: &'static str, params: DatabaseParams) -> Result<(), ()> {
+        let (sender, receiver) = unbounded();
+        let _ = self.task_sender.send_blocking(DatabaseTask::WaitExecute(query, params, sender));
+        receiver.recv_blocking().map(|_| ()).map_err(|_| ())
+    }
+
     /// Execute function with receiver callback intended for insert commands (returns row id)
     pub async fn insert(&self, query: &'static str, params: DatabaseParams) -> Option<usize> {
         let (sender, receiver) = unbounded();
@@ -152,6 +167,24 @@ impl DataLink {
             true => Err(ResonateError::GenericError)
         }
     }
+
+    /// Collect all results on the calling thread, for use outside of the async runtime
+    pub fn blocking_query_map(
+        &self, query: &'static str, params: DatabaseParams
+    ) -> Result<Vec<Vec<DatabaseParam>>, ResonateError> {
+        let receiver = self.query_stream(query, params);
+
+        let mut values = Vec::new();
+        while let Ok(item) = receiver.recv_blocking() {
+            match item {
+                ItemStream::End => return Ok(values),
+                ItemStream::Error => return Err(ResonateError::GenericError),
+                ItemStream::Value(v) => values.push(v)
+            };
+        }
+
+        Ok(values)
+    }
 }
 
 impl Database {
@@ -0,0 +1,459 @@
+use std::collections::HashMap;
+
+use std::fs::metadata;
+
+use async_channel::Receiver;
+
+use crate::backend::database_interface::DatabaseInterface;
+use crate::backend::database_manager::DataLink;
+use crate::backend::error::DownloadError;
+use crate::backend::music::Song;
+use crate::backend::web::DownloadHandle;
+use crate::backend::web::DownloadProgress;
+use crate::backend::web::StopRequest;
+
+/// Bulk downloads, such as a whole playlist
+pub const PRIORITY_BACKGROUND: usize = 0;
+
+/// A song asked for on its own, starts ahead of bulk downloads
+pub const PRIORITY_NORMAL: usize = 1;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DownloadState {
+    Queued,
+    Active,
+    Failed(DownloadError),
+    Done
+}
+
+impl DownloadState {
+    /// The state and error columns of the Downloads table
+    pub fn to_row(self) -> (usize, usize) {
+        match self {
+            DownloadState::Queued => (0, 0),
+            DownloadState::Active => (1, 0),
+            DownloadState::Failed(error) => (
+                2, DownloadError::ALL.iter().position(|other| *other == error).map(|idx| idx + 1).unwrap_or(0)
+            ),
+            DownloadState::Done => (3, 0)
+        }
+    }
+
+    pub fn from_row(state: usize, error: usize) -> Self {
+        match state {
+            1 => DownloadState::Active,
+            2 => DownloadState::Failed(
+                error.checked_sub(1).and_then(|idx| DownloadError::ALL.get(idx).copied()).unwrap_or(DownloadError::Unknown)
+            ),
+            3 => DownloadState::Done,
+            _ => DownloadState::Queued
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct DownloadEntry {
+    pub song: Song,
+    pub state: DownloadState,
+    pub priority: usize,
+    pub size: Option<u64>          // Bytes on disk, once finished
+}
+
+impl DownloadEntry {
+    fn read_size(&mut self) {
+        self.size = self.song.music_path.as_ref().and_then(|path| metadata(path).ok()).map(|metadata| metadata.len());
+    }
+}
+
+/// Owns the download queue. Decides what runs next and keeps the Downloads table in step,
+/// so queued and interrupted downloads carry on after a restart.
+pub struct DownloadScheduler {
+    database: DataLink,
+    entries: Vec<DownloadEntry>,                                  // Highest priority first, then oldest first
+    states: HashMap<String, DownloadState>,                       // By yt_id, for quick lookups while drawing
+    active: HashMap<String, (DownloadProgress, DownloadHandle)>,  // By yt_id
+    max_concurrency: usize,
+    paused: bool
+}
+
+impl DownloadScheduler {
+    pub fn new(database: DataLink, max_concurrency: usize) -> Self {
+        Self {
+            database,
+            entries: Vec::new(),
+            states: HashMap::new(),
+            active: HashMap::new(),
+            max_concurrency,
+            paused: false
+        }
+    }
+
+    fn position(&self, yt_id: &str) -> Option<usize> {
+        self.entries.iter().position(|entry| entry.song.yt_id == yt_id)
+    }
+
+    fn set_state(&mut self, idx: usize, state: DownloadState) {
+        let entry = &mut self.entries[idx];
+        entry.state = state;
+        self.states.insert(entry.song.yt_id.clone(), state);
+        DatabaseInterface::update_download_state(self.database.clone(), entry.song.id, state);
+    }
+
+    fn remove(&mut self, idx: usize) {
+        let entry = self.entries.remove(idx);
+        self.states.remove(&entry.song.yt_id);
+        DatabaseInterface::remove_download(self.database.clone(), entry.song.id);
+    }
+
+    /// Take in the queue saved last session. Anything that was running when the app closed is queued again.
+    pub fn restore(&mut self, saved: Vec<DownloadEntry>) {
+        for mut entry in saved {
+            if self.states.contains_key(&entry.song.yt_id) { continue; }
+
+            entry.state = match entry.state {
+                // Finished by something else since, or the app closed before it could be marked
+                DownloadState::Queued | DownloadState::Active if entry.song.music_path.is_some() => DownloadState::Done,
+                DownloadState::Active => DownloadState::Queued,
+                state => state
+            };
+
+            if entry.state == DownloadState::Done {
+                entry.read_size();
+            }
+
+            DatabaseInterface::update_download_state(self.database.clone(), entry.song.id, entry.state);
+            self.states.insert(entry.song.yt_id.clone(), entry.state);
+            self.entries.push(entry);
+        }
+
+        // Stable, so the saved order holds within each priority
+        self.entries.sort_by_key(|entry| std::cmp::Reverse(entry.priority));
+    }
+
+    /// Add a song to the back of its priority. Songs that are already downloaded, queued or running are left alone,
+    /// failed and finished ones are queued again.
+    pub fn enqueue(&mut self, song: Song, priority: usize) -> bool {
+        if song.music_path.as_ref().is_some_and(|path| path.exists()) { return false; }
+
+        if let Some(idx) = self.position(&song.yt_id) {
+            match self.entries[idx].state {
+                DownloadState::Queued | DownloadState::Active => return false,
+                _ => {
+                    let entry = self.entries.remove(idx);
+                    self.states.remove(&entry.song.yt_id);
+                }
+            }
+        }
+
+        DatabaseInterface::insert_download(self.database.clone(), song.id, DownloadState::Queued, priority);
+
+        let idx = self.entries.iter().position(|entry| entry.priority < priority).unwrap_or(self.entries.len());
+        self.states.insert(song.yt_id.clone(), DownloadState::Queued);
+        self.entries.insert(idx, DownloadEntry { song, state: DownloadState::Queued, priority, size: None });
+        true
+    }
+
+    /// Mark as many queued downloads active as the concurrency limit allows,
+    /// handing back each song with the receiver `download_song` listens to for cancellation
+    pub fn start(&mut self) -> Vec<(Song, Receiver<StopRequest>)> {
+        let mut started = Vec::new();
+        if self.paused { return started; }
+
+        while self.active.len() < self.max_concurrency.max(1) {
+            let idx = match self.entries.iter().position(|entry| entry.state == DownloadState::Queued) {
+                Some(idx) => idx,
+                None => break
+            };
+
+            self.set_state(idx, DownloadState::Active);
+            let song = self.entries[idx].song.clone();
+            let (handle, cancel) = DownloadHandle::new();
+            self.active.insert(song.yt_id.clone(), (DownloadProgress::new(song.yt_id.clone()), handle));
+            started.push((song, cancel));
+        }
+
+        started
+    }
+
+    pub fn update_progress(&mut self, progress: DownloadProgress) {
+        if let Some((current, _)) = self.active.get_mut(&progress.yt_id) {
+            *current = progress;
+        }
+    }
+
+    pub fn finished(&mut self, song: &Song) {
+        self.active.remove(&song.yt_id);
+        if let Some(idx) = self.position(&song.yt_id) {
+            self.entries[idx].song = song.clone();
+            self.entries[idx].read_size();
+            DatabaseInterface::update_song_extension(self.database.clone(), song);
+            self.set_state(idx, DownloadState::Done);
+        }
+    }
+
+    pub fn failed(&mut self, song: &Song, error: DownloadError) {
+        self.active.remove(&song.yt_id);
+        if let Some(idx) = self.position(&song.yt_id) {
+            self.set_state(idx, DownloadState::Failed(error));
+        }
+    }
+
+    pub fn stopped(&mut self, song: &Song, request: StopRequest) {
+        self.active.remove(&song.yt_id);
+        let idx = match self.position(&song.yt_id) {
+            Some(idx) => idx,
+            None => return
+        };
+
+        match request {
+            // Back where it was in the queue, ready for when downloads resume
+            StopRequest::Pause => self.set_state(idx, DownloadState::Queued),
+            StopRequest::Cancel => self.remove(idx)
+        }
+    }
+
+    /// Stop a running download, or take a queued one out of the queue
+    pub fn cancel(&mut self, yt_id: &str) {
+        // A running download is removed once it reports back as cancelled
+        if let Some((_, handle)) = self.active.get(yt_id) {
+            handle.cancel();
+        } else if let Some(idx) = self.position(yt_id) {
+            if self.entries[idx].state == DownloadState::Queued {
+                self.remove(idx);
+            }
+        }
+    }
+
+    pub fn cancel_song(&mut self, song_id: usize) {
+        if let Some(yt_id) = self.entries.iter().find(|entry| entry.song.id == song_id).map(|entry| entry.song.yt_id.clone()) {
+            self.cancel(&yt_id);
+        }
+    }
+
+    /// Stop every running download without forgetting it, and start nothing new until resumed
+    pub fn pause(&mut self) {
+        self.paused = true;
+        self.active.values().for_each(|(_, handle)| handle.pause());
+    }
+
+    /// Allow downloads to start again, the caller should follow with `start`
+    pub fn resume(&mut self) {
+        self.paused = false;
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused
+    }
+
+    /// Queue every failed download again, behind whatever is already waiting
+    pub fn retry_failed(&mut self) {
+        let failed: Vec<DownloadEntry> = self.entries.iter()
+            .filter(|entry| matches!(entry.state, DownloadState::Failed(_)))
+            .cloned()
+            .collect();
+
+        for entry in failed {
+            self.enqueue(entry.song, entry.priority);
+        }
+    }
+
+    /// Forget finished downloads, the songs themselves stay
+    pub fn clear_completed(&mut self) {
+        let (done, entries) = std::mem::take(&mut self.entries).into_iter()
+            .partition::<Vec<DownloadEntry>, _>(|entry| entry.state == DownloadState::Done);
+
+        self.entries = entries;
+        done.iter().for_each(|entry| { self.states.remove(&entry.song.yt_id); });
+        DatabaseInterface::remove_downloads_by_state(self.database.clone(), DownloadState::Done);
+    }
+
+    /// Takes effect as downloads finish, running ones are never stopped to meet a lower limit
+    pub fn set_max_concurrency(&mut self, max_concurrency: usize) {
+        self.max_concurrency = max_concurrency;
+    }
+
+    pub fn max_concurrency(&self) -> usize {
+        self.max_concurrency
+    }
+
+    pub fn entries(&self) -> &[DownloadEntry] {
+        &self.entries
+    }
+
+    pub fn progress(&self, yt_id: &str) -> Option<&DownloadProgress> {
+        self.active.get(yt_id).map(|(progress, _)| progress)
+    }
+
+    pub fn is_queued(&self, yt_id: &str) -> bool {
+        self.states.get(yt_id) == Some(&DownloadState::Queued)
+    }
+
+    pub fn error(&self, yt_id: &str) -> Option<&DownloadError> {
+        match self.states.get(yt_id) {
+            Some(DownloadState::Failed(error)) => Some(error),
+            _ => None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+    use std::time::Duration;
+
+    use super::*;
+
+    fn song(id: usize) -> Song {
+        Song::new(id, format!("yt{id}"), String::new(), String::new(), None, Duration::ZERO, None)
+    }
+
+    /// Nothing reads the other end, so every write is dropped
+    fn scheduler(max_concurrency: usize) -> DownloadScheduler {
+        let (sender, _) = async_channel::unbounded();
+        DownloadScheduler::new(DataLink::new(sender), max_concurrency)
+    }
+
+    fn ids(scheduler: &DownloadScheduler) -> Vec<usize> {
+        scheduler.entries().iter().map(|entry| entry.song.id).collect()
+    }
+
+    fn started(scheduler: &mut DownloadScheduler) -> Vec<usize> {
+        scheduler.start().into_iter().map(|(song, _)| song.id).collect()
+    }
+
+    #[test]
+    fn state_survives_a_round_trip_through_its_row() {
+        let states = [DownloadState::Queued, DownloadState::Active, DownloadState::Done].into_iter()
+            .chain(DownloadError::ALL.iter().map(|error| DownloadState::Failed(*error)));
+
+        for state in states {
+            let (row, error) = state.to_row();
+            assert_eq!(DownloadState::from_row(row, error), state);
+        }
+    }
+
+    #[test]
+    fn unknown_rows_fall_back() {
+        assert_eq!(DownloadState::from_row(2, 0), DownloadState::Failed(DownloadError::Unknown));
+        assert_eq!(DownloadState::from_row(2, 99), DownloadState::Failed(DownloadError::Unknown));
+        assert_eq!(DownloadState::from_row(99, 0), DownloadState::Queued);
+    }
+
+    #[test]
+    fn normal_priority_goes_ahead_of_background() {
+        let mut scheduler = scheduler(1);
+        scheduler.enqueue(song(1), PRIORITY_BACKGROUND);
+        scheduler.enqueue(song(2), PRIORITY_BACKGROUND);
+        scheduler.enqueue(song(3), PRIORITY_NORMAL);
+        scheduler.enqueue(song(4), PRIORITY_NORMAL);
+        assert_eq!(ids(&scheduler), [3, 4, 1, 2]);
+    }
+
+    #[test]
+    fn enqueue_ignores_songs_already_waiting_or_running() {
+        let mut scheduler = scheduler(1);
+        assert!(scheduler.enqueue(song(1), PRIORITY_NORMAL));
+        assert!(!scheduler.enqueue(song(1), PRIORITY_NORMAL));
+        started(&mut scheduler);
+        assert!(!scheduler.enqueue(song(1), PRIORITY_NORMAL));
+        assert_eq!(ids(&scheduler), [1]);
+    }
+
+    #[test]
+    fn start_fills_the_concurrency_limit_in_order() {
+        let mut scheduler = scheduler(2);
+        for id in 1..=4 {
+            scheduler.enqueue(song(id), PRIORITY_BACKGROUND);
+        }
+
+        assert_eq!(started(&mut scheduler), [1, 2]);
+        assert!(started(&mut scheduler).is_empty());
+
+        let mut finished = song(1);
+        finished.music_path = Some(PathBuf::from("yt1.m4a"));
+        scheduler.finished(&finished);
+        assert_eq!(scheduler.entries()[0].state, DownloadState::Done);
+        assert_eq!(started(&mut scheduler), [3]);
+    }
+
+    #[test]
+    fn lowering_the_limit_waits_for_running_downloads() {
+        let mut scheduler = scheduler(3);
+        for id in 1..=4 {
+            scheduler.enqueue(song(id), PRIORITY_NORMAL);
+        }
+        assert_eq!(started(&mut scheduler), [1, 2, 3]);
+
+        scheduler.set_max_concurrency(1);
+        scheduler.failed(&song(1), DownloadError::Network);
+        scheduler.failed(&song(2), DownloadError::Network);
+        assert!(started(&mut scheduler).is_empty());
+
+        scheduler.failed(&song(3), DownloadError::Network);
+        assert_eq!(started(&mut scheduler), [4]);
+    }
+
+    #[test]
+    fn retried_failures_queue_behind_waiting_songs() {
+        let mut scheduler = scheduler(1);
+        scheduler.enqueue(song(1), PRIORITY_NORMAL);
+        scheduler.enqueue(song(2), PRIORITY_NORMAL);
+        started(&mut scheduler);
+        scheduler.failed(&song(1), DownloadError::Unavailable);
+        assert_eq!(scheduler.error("yt1"), Some(&DownloadError::Unavailable));
+
+        scheduler.retry_failed();
+        assert_eq!(ids(&scheduler), [2, 1]);
+        assert!(scheduler.is_queued("yt1"));
+        assert_eq!(started(&mut scheduler), [2]);
+    }
+
+    #[test]
+    fn pausing_keeps_the_queue_in_place() {
+        let mut scheduler = scheduler(1);
+        scheduler.enqueue(song(1), PRIORITY_NORMAL);
+        scheduler.enqueue(song(2), PRIORITY_NORMAL);
+        started(&mut scheduler);
+
+        scheduler.pause();
+        scheduler.stopped(&song(1), StopRequest::Pause);
+        assert!(started(&mut scheduler).is_empty());
+        assert_eq!(ids(&scheduler), [1, 2]);
+
+        scheduler.resume();
+        assert_eq!(started(&mut scheduler), [1]);
+    }
+
+    #[test]
+    fn cancelling_removes_the_entry() {
+        let mut scheduler = scheduler(1);
+        scheduler.enqueue(song(1), PRIORITY_NORMAL);
+        scheduler.enqueue(song(2), PRIORITY_NORMAL);
+        started(&mut scheduler);
+
+        // Queued songs go straight away, running ones once they report back
+        scheduler.cancel("yt2");
+        scheduler.cancel("yt1");
+        assert_eq!(ids(&scheduler), [1]);
+        scheduler.stopped(&song(1), StopRequest::Cancel);
+        assert!(ids(&scheduler).is_empty());
+    }
+
+    #[test]
+    fn restore_requeues_interrupted_downloads_by_priority() {
+        let entry = |id: usize, state: DownloadState, priority: usize| DownloadEntry { song: song(id), state, priority, size: None };
+
+        let mut scheduler = scheduler(1);
+        scheduler.restore(vec![
+            entry(1, DownloadState::Queued, PRIORITY_BACKGROUND),
+            entry(2, DownloadState::Active, PRIORITY_NORMAL),
+            entry(3, DownloadState::Failed(DownloadError::DiskFull), PRIORITY_BACKGROUND),
+            entry(4, DownloadState::Queued, PRIORITY_NORMAL)
+        ]);
+
+        assert_eq!(ids(&scheduler), [2, 4, 1, 3]);
+        assert_eq!(scheduler.entries()[0].state, DownloadState::Queued);
+        assert_eq!(scheduler.entries()[3].state, DownloadState::Failed(DownloadError::DiskFull));
+        assert_eq!(started(&mut scheduler), [2]);
+    }
+}
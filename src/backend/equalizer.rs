@@ -0,0 +1,232 @@
+use std::f32::consts::PI;
+use std::fmt::Formatter;
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::time::Duration;
+
+use rodio::Sample;
+use rodio::Source;
+use rodio::source::SeekError;
+
+pub const BANDS: usize = 10;
+pub const FREQUENCIES: [f32; BANDS] = [31.0, 62.0, 125.0, 250.0, 500.0, 1000.0, 2000.0, 4000.0, 8000.0, 16000.0];
+pub const MAX_GAIN: f32 = 12.0;
+
+/// Roughly one octave per band
+const Q: f32 = 1.41;
+
+/// How many samples are processed between checks for new gains
+const CONTROL_INTERVAL: usize = 1024;
+
+pub type Gains = [f32; BANDS];
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct EqualizerPreset {
+    pub id: Option<usize>,   // None for built in presets
+    pub name: String,
+    pub gains: Gains
+}
+
+impl EqualizerPreset {
+    fn builtin(name: &str, gains: Gains) -> Self {
+        Self { id: None, name: String::from(name), gains }
+    }
+
+    pub fn builtins() -> Vec<EqualizerPreset> {
+        vec![
+            Self::builtin("Flat",         [0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0]),
+            Self::builtin("Bass Boost",   [6.0, 5.0, 4.0, 2.0, 0.5, 0.0, 0.0, 0.0, 0.0, 0.0]),
+            Self::builtin("Bass Cut",     [-6.0, -5.0, -4.0, -2.0, -0.5, 0.0, 0.0, 0.0, 0.0, 0.0]),
+            Self::builtin("Treble Boost", [0.0, 0.0, 0.0, 0.0, 0.0, 0.5, 2.0, 4.0, 5.0, 6.0]),
+            Self::builtin("Vocal",        [-2.0, -2.0, -1.0, 1.0, 3.0, 4.0, 3.0, 1.0, 0.0, -1.0]),
+            Self::builtin("Rock",         [4.0, 3.0, 2.0, 0.0, -1.0, -1.0, 1.0, 2.5, 3.5, 4.0]),
+            Self::builtin("Electronic",   [5.0, 4.0, 1.5, 0.0, -1.5, 1.5, 0.5, 1.5, 4.0, 5.0]),
+            Self::builtin("Acoustic",     [3.0, 3.0, 2.0, 1.0, 1.5, 1.5, 2.5, 3.0, 2.5, 1.5]),
+            Self::builtin("Loudness",     [5.0, 4.0, 1.0, 0.0, -1.0, 0.0, -0.5, 1.0, 4.0, 5.0]),
+        ]
+    }
+
+    /// Gains are stored in the database as a comma separated list
+    pub fn serialise_gains(gains: &Gains) -> String {
+        gains.iter().map(|gain| format!("{gain:.1}")).collect::<Vec<String>>().join(",")
+    }
+
+    pub fn parse_gains(string: &str) -> Option<Gains> {
+        let values = string.split(',')
+            .map(|value| value.trim().parse::<f32>().ok())
+            .collect::<Option<Vec<f32>>>()?;
+
+        let mut gains = [0f32; BANDS];
+        if values.len() != BANDS { return None; }
+        for (gain, value) in gains.iter_mut().zip(values) {
+            *gain = value.clamp(-MAX_GAIN, MAX_GAIN);
+        }
+        Some(gains)
+    }
+}
+
+impl std::fmt::Display for EqualizerPreset {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), std::fmt::Error> {
+        write!(f, "{}", self.name)
+    }
+}
+
+struct EqualizerState {
+    gains: Gains,
+    version: usize
+}
+
+/// Shared between the audio thread and every `Equalizer` source so gains can change mid-song
+#[derive(Clone)]
+pub struct EqualizerControl {
+    state: Arc<Mutex<EqualizerState>>
+}
+
+impl EqualizerControl {
+    pub fn new(gains: Gains) -> Self {
+        Self { state: Arc::new(Mutex::new(EqualizerState { gains, version: 0 })) }
+    }
+
+    pub fn set(&self, gains: Gains) {
+        if let Ok(mut state) = self.state.lock() {
+            state.gains = gains;
+            state.version += 1;
+        }
+    }
+
+    fn changed_since(&self, version: usize) -> Option<(Gains, usize)> {
+        match self.state.lock() {
+            Ok(state) if state.version != version => Some((state.gains, state.version)),
+            _ => None
+        }
+    }
+}
+
+/// RBJ peaking filter, direct form II transposed
+#[derive(Clone, Copy, Default)]
+struct Biquad {
+    b: [f32; 3],
+    a: [f32; 2],
+    z: [f32; 2]
+}
+
+impl Biquad {
+    fn peaking(frequency: f32, gain: f32, sample_rate: u32) -> Self {
+        let nyquist = sample_rate as f32 / 2.0;
+        if gain == 0.0 || frequency >= nyquist {
+            return Self { b: [1.0, 0.0, 0.0], ..Default::default() };
+        }
+
+        let a = 10f32.powf(gain / 40.0);
+        let w0 = 2.0 * PI * frequency / sample_rate as f32;
+        let alpha = w0.sin() / (2.0 * Q);
+        let cos = w0.cos();
+        let a0 = 1.0 + alpha / a;
+
+        Self {
+            b: [(1.0 + alpha * a) / a0, (-2.0 * cos) / a0, (1.0 - alpha * a) / a0],
+            a: [(-2.0 * cos) / a0, (1.0 - alpha / a) / a0],
+            z: [0.0; 2]
+        }
+    }
+
+    fn retune(&mut self, other: Biquad) {
+        self.b = other.b;
+        self.a = other.a;
+    }
+
+    fn process(&mut self, input: f32) -> f32 {
+        let output = self.b[0] * input + self.z[0];
+        self.z[0] = self.b[1] * input - self.a[0] * output + self.z[1];
+        self.z[1] = self.b[2] * input - self.a[1] * output;
+        output
+    }
+}
+
+/// Ten band equalizer placed in front of the sink
+pub struct Equalizer<S> {
+    source: S,
+    control: EqualizerControl,
+    version: Option<usize>,
+    filters: Vec<[Biquad; BANDS]>,
+    bypass: bool,
+    channel: usize,
+    counter: usize
+}
+
+impl<S> Equalizer<S> where S: Source, S::Item: Sample {
+    pub fn new(source: S, control: EqualizerControl) -> Self {
+        let channels = source.channels().max(1) as usize;
+        let mut equalizer = Self {
+            source,
+            control,
+            version: None,
+            filters: vec![[Biquad::default(); BANDS]; channels],
+            bypass: true,
+            channel: 0,
+            counter: 0
+        };
+        equalizer.refresh();
+        equalizer
+    }
+
+    /// Pick up new gains, keeping the filter state so changes don't click
+    fn refresh(&mut self) {
+        let (gains, version) = match self.control.changed_since(self.version.unwrap_or(usize::MAX)) {
+            Some(update) => update,
+            None => return
+        };
+
+        let sample_rate = self.source.sample_rate();
+        self.version = Some(version);
+        self.bypass = gains.iter().all(|gain| *gain == 0.0);
+
+        for channel in self.filters.iter_mut() {
+            for (band, filter) in channel.iter_mut().enumerate() {
+                filter.retune(Biquad::peaking(FREQUENCIES[band], gains[band], sample_rate));
+            }
+        }
+    }
+}
+
+impl<S> Iterator for Equalizer<S> where S: Source, S::Item: Sample {
+    type Item = f32;
+
+    fn next(&mut self) -> Option<f32> {
+        let sample = self.source.next()?.to_f32();
+
+        self.counter += 1;
+        if self.counter >= CONTROL_INTERVAL {
+            self.counter = 0;
+            self.refresh();
+        }
+
+        let channel = self.channel;
+        self.channel = (self.channel + 1) % self.filters.len();
+
+        if self.bypass {
+            return Some(sample);
+        }
+
+        Some(self.filters[channel].iter_mut().fold(sample, |value, filter| filter.process(value)))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.source.size_hint()
+    }
+}
+
+impl<S> Source for Equalizer<S> where S: Source, S::Item: Sample {
+    fn current_frame_len(&self) -> Option<usize> { self.source.current_frame_len() }
+    fn channels(&self) -> u16 { self.source.channels() }
+    fn sample_rate(&self) -> u32 { self.source.sample_rate() }
+    fn total_duration(&self) -> Option<Duration> { self.source.total_duration() }
+
+    fn try_seek(&mut self, pos: Duration) -> Result<(), SeekError> {
+        for channel in self.filters.iter_mut() {
+            for filter in channel.iter_mut() { filter.z = [0.0; 2]; }
+        }
+        self.channel = 0;
+        self.source.try_seek(pos)
+    }
+}
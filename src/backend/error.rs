@@ -10,3 +10,44 @@ pub enum ResonateError {
     AlreadyExists,
     STDOUTError
 }
+
+/// Why a download didn't produce a song, worked out from what yt-dlp printed and how it exited
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DownloadError {
+    ExecutableMissing,
+    Network,
+    Unavailable,        // Removed, private, members only or age restricted
+    GeoBlocked,
+    DiskFull,
+    PostProcessing,     // Downloaded, but converting or tagging it went wrong
+    Unknown
+}
+
+impl DownloadError {
+    pub const ALL: [DownloadError; 7] = [
+        DownloadError::ExecutableMissing,
+        DownloadError::Network,
+        DownloadError::Unavailable,
+        DownloadError::GeoBlocked,
+        DownloadError::DiskFull,
+        DownloadError::PostProcessing,
+        DownloadError::Unknown
+    ];
+
+    /// Worth trying again after a pause, everything else will fail the same way next time
+    pub fn is_transient(&self) -> bool {
+        matches!(self, DownloadError::Network | DownloadError::Unknown)
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            DownloadError::ExecutableMissing => "yt-dlp not found",
+            DownloadError::Network => "Network error",
+            DownloadError::Unavailable => "Video unavailable",
+            DownloadError::GeoBlocked => "Not available in your country",
+            DownloadError::DiskFull => "Disk full",
+            DownloadError::PostProcessing => "Conversion failed",
+            DownloadError::Unknown => "Download failed"
+        }
+    }
+}
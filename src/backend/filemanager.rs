@@ -4,13 +4,22 @@ use std::path::PathBuf;
 use std::fs::read_dir;
 use std::fs::create_dir_all;
 use std::fs::create_dir;
+use std::fs::copy;
+use std::fs::rename;
+use std::fs::remove_file;
+use std::process::Stdio;
 
 use image::Luma;
 use image::ImageBuffer;
 use directories::ProjectDirs;
 use youtube_dl::downloader::YoutubeDlFetcher;
+use tokio::process::Command;
 
 use crate::backend::error::ResonateError;
+use crate::backend::settings::Settings;
+use crate::backend::tools::locate;
+use crate::backend::tools::Tool;
+use crate::backend::tools::ToolSource;
 
 #[derive(Clone)]
 pub struct DataDir {
@@ -18,7 +27,9 @@ pub struct DataDir {
     music: PathBuf,
     dependencies: PathBuf,
     thumbnails: PathBuf,
-    dlp_path: Option<PathBuf>
+    waveforms: PathBuf,
+    dlp: Option<(PathBuf, ToolSource)>,
+    ffmpeg: Option<(PathBuf, ToolSource)>
 }
 
 impl DataDir {
@@ -33,6 +44,7 @@ impl DataDir {
         let music = root.join("music");
         let dependencies = root.join("dependencies");
         let thumbnails = root.join("thumbnails");
+        let waveforms = root.join("waveforms");
 
         let _ = create_dir_all(&root);
         if !root.exists() { return Err(error); }
@@ -55,6 +67,12 @@ impl DataDir {
             Err(_) => return Err(error)
         };
 
+        match create_dir(&waveforms) {
+            Ok(_) => (),
+            Err(e) if e.kind() == ErrorKind::AlreadyExists => (),
+            Err(_) => return Err(error)
+        };
+
         let _ = if thumbnails.exists() {
             let default_thumbnail = thumbnails.join("default_thumbnail.png");
             if !default_thumbnail.exists() {
@@ -66,57 +84,81 @@ impl DataDir {
             panic!("COULDN'T CREATE DEFAULT THUMBNAIL");
         };
 
-        let matching_entry = match read_dir(&dependencies) {
-            Ok(entries) => entries,
-            Err(_) => return Err(ResonateError::DirectoryNotFound)
-        }.find(|entry| match entry {
-                Err(_) => false,
-                Ok(entry) => {
-                    entry.path().to_string_lossy().to_string().contains("yt-dlp")
-                }
-            });
-
-        let dlp_path = matching_entry.map(|entry| entry.unwrap().path().to_path_buf());
-        Ok(Self { music, dependencies, thumbnails, root, dlp_path })
+        let dlp = find_dlp(&dependencies)?.map(|path| (path, ToolSource::Bundled));
+        Ok(Self { music, dependencies, thumbnails, waveforms, root, dlp, ffmpeg: None })
+    }
+
+    /// Apply overrides from the environment and settings, falling back to the bundled copies and then PATH
+    pub fn locate_tools(&mut self, settings: &Settings) {
+        let bundled_dlp = find_dlp(&self.dependencies).ok().flatten();
+        let bundled_ffmpeg = self.dependencies.join(Tool::Ffmpeg.executable());
+
+        self.dlp = locate(Tool::Dlp, settings.dlp_path.as_deref(), bundled_dlp);
+        self.ffmpeg = locate(Tool::Ffmpeg, settings.ffmpeg_path.as_deref(), Some(bundled_ffmpeg));
+
+        for (tool, found) in [(Tool::Dlp, &self.dlp), (Tool::Ffmpeg, &self.ffmpeg)] {
+            match found {
+                Some((path, source)) => println!("[DIRECTORIES] Using {} from {} at {path:?}", tool.as_str(), source.as_str()),
+                None => println!("[DIRECTORIES] {} not found", tool.as_str())
+            }
+        }
     }
 
+    /// yt-dlp installed into the dependencies folder
     pub fn take_dlp_path(&mut self, dlp_path: PathBuf) {
-        self.dlp_path = Some(dlp_path);
+        self.dlp = Some((dlp_path, ToolSource::Bundled));
     }
 
     pub fn get_root_ref(&self) -> &Path { self.root.as_path() }
     pub fn get_music_ref(&self) -> &Path { self.music.as_path() }
     pub fn get_dependencies_ref(&self) -> &Path { self.dependencies.as_path() }
     pub fn get_thumbnails_ref(&self) -> &Path { self.thumbnails.as_path() }
+    pub fn get_waveforms_ref(&self) -> &Path { self.waveforms.as_path() }
     pub fn get_dlp_ref(&self) -> Option<&Path> {
-        match &self.dlp_path {
-            Some(dlp_path) => Some(dlp_path.as_path()),
+        match &self.dlp {
+            Some((dlp_path, _)) => Some(dlp_path.as_path()),
             None => None
         }
     }
+    pub fn get_dlp_source(&self) -> Option<ToolSource> { self.dlp.as_ref().map(|(_, source)| *source) }
+    pub fn get_ffmpeg_ref(&self) -> Option<&Path> { self.ffmpeg.as_ref().map(|(path, _)| path.as_path()) }
+    pub fn get_ffmpeg_source(&self) -> Option<ToolSource> { self.ffmpeg.as_ref().map(|(_, source)| *source) }
+
+    /// Only the bundled yt-dlp is ours to update, anything else belongs to the user or their package manager
+    pub fn is_dlp_managed(&self) -> bool {
+        self.get_dlp_source() == Some(ToolSource::Bundled)
+    }
+}
+
+/// Copy of the working yt-dlp kept while updating. Named so `find_dlp` never mistakes it for the real one.
+const DLP_BACKUP: &str = "dlp-rollback";
+
+/// The yt-dlp executable, e.g. `yt-dlp`, `yt-dlp.exe` or `yt-dlp_macos`.
+/// Leftovers such as `yt-dlp.exe.old` from a self-update or a `.part` download are not it.
+fn is_dlp_executable(path: &Path) -> bool {
+    let named = path.file_name().is_some_and(|name| name.to_string_lossy().starts_with("yt-dlp"));
+    let extension = path.extension().map(|extension| extension.to_string_lossy().to_lowercase());
+    path.is_file() && named && matches!(extension.as_deref(), None | Some("exe"))
+}
+
+fn find_dlp(dependencies: &Path) -> Result<Option<PathBuf>, ResonateError> {
+    match read_dir(dependencies) {
+        Ok(entries) => Ok(entries.filter_map(|entry| entry.ok()).map(|entry| entry.path()).find(|path| is_dlp_executable(path))),
+        Err(_) => Err(ResonateError::DirectoryNotFound)
+    }
 }
 
 /// Attempt to install yt-dlp. If it is already installed, return the path
 pub async fn install_dlp(target: PathBuf) -> Result<PathBuf, ResonateError> {
     println!("[DIRECTORIES] Attempting to download yt-dlp to {target:?}");
     // Check if some yt-dlp file already exists in the dependencies
-    let existing_path_option = match read_dir(&target) {
-        Ok(contents) => contents.into_iter().filter_map(|item| item.ok()).find(|entry| {
-            match entry.path().is_file() {
-                true => {
-                    entry.path().to_string_lossy().to_string().contains("yt-dlp")
-                }
-                false => false
-            }
-        }),
-        Err(_) => return Err(ResonateError::DirectoryNotFound)
-    };
+    let existing_path_option = find_dlp(&target)?;
 
     println!("[DIRECTORIES] Existing installation: {existing_path_option:?}");
 
     // If there is an existing executable, return the path to it
     if let Some(path) = existing_path_option {
-        return Ok(path.path());
+        return Ok(path);
     }
 
     // If not, attempt to download
@@ -125,3 +167,86 @@ pub async fn install_dlp(target: PathBuf) -> Result<PathBuf, ResonateError> {
         Err(_) => Err(ResonateError::NetworkError)
     }
 }
+
+fn dlp_command(dlp_path: &Path) -> Command {
+    let mut cmd = Command::new(dlp_path);
+    cmd.stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .kill_on_drop(true);
+
+    #[cfg(windows)]
+    {
+        cmd.creation_flags(0x08000000);
+    }
+
+    cmd
+}
+
+/// The version yt-dlp reports, e.g. `2024.08.06`. None if it won't run.
+pub async fn dlp_version(dlp_path: PathBuf) -> Option<String> {
+    let output = dlp_command(&dlp_path).arg("--version").output().await.ok()?;
+    let version = String::from_utf8_lossy(&output.stdout).trim().to_string();
+
+    match output.status.success() && !version.is_empty() {
+        true => Some(version),
+        false => None
+    }
+}
+
+/// Whether the installed version satisfies a pin such as `2024.08.06` or `stable@2024.08.06`.
+/// A pin to a channel alone, such as `nightly`, is only followed when updating.
+pub fn is_pinned_version(pin: &str, version: &str) -> bool {
+    let tag = pin.rsplit('@').next().unwrap_or(pin);
+    !tag.starts_with(|c: char| c.is_ascii_digit()) || tag == version
+}
+
+/// Check a yt-dlp binary runs and its extractors load, without touching the network,
+/// so being offline or rate limited can't make a good update look broken
+async fn smoke_test(dlp_path: &Path) -> Option<String> {
+    let version = dlp_version(dlp_path.to_path_buf()).await?;
+    let output = dlp_command(dlp_path).arg("--list-extractors").output().await.ok()?;
+    let youtube = String::from_utf8_lossy(&output.stdout).lines().any(|line| line.trim() == "youtube");
+
+    match output.status.success() && youtube {
+        true => Some(version),
+        false => None
+    }
+}
+
+/// Update yt-dlp in place with its own updater, to the latest release or to `pin` if given.
+/// If the update fails, or the new binary fails a smoke test, the previous binary is put back.
+/// Returns the version now installed.
+pub async fn update_dlp(dlp_path: PathBuf, pin: Option<String>) -> Result<String, ResonateError> {
+    let backup = dlp_path.with_file_name(DLP_BACKUP);
+    if copy(&dlp_path, &backup).is_err() {
+        return Err(ResonateError::DirectoryNotFound);
+    }
+
+    let mut cmd = dlp_command(&dlp_path);
+    match pin.as_ref() {
+        Some(pin) => cmd.arg("--update-to").arg(pin),
+        None => cmd.arg("-U")
+    };
+
+    let updated = matches!(cmd.status().await, Ok(status) if status.success());
+    let version = match updated {
+        true => smoke_test(&dlp_path).await,
+        false => None
+    };
+
+    match version {
+        Some(version) => {
+            let _ = remove_file(&backup);
+            println!("[DIRECTORIES] yt-dlp is now {version}");
+            Ok(version)
+        }
+        None => {
+            println!("[DIRECTORIES] yt-dlp update failed, rolling back");
+            let _ = rename(&backup, &dlp_path);
+            Err(match updated {
+                true => ResonateError::GenericError,
+                false => ResonateError::NetworkError
+            })
+        }
+    }
+}
@@ -0,0 +1,110 @@
+use std::time::Duration;
+use std::time::Instant;
+use std::time::SystemTime;
+use std::time::UNIX_EPOCH;
+
+use crate::backend::music::Song;
+use crate::backend::tempo::MAX_SPEED;
+
+/// Last.fm ignores anything shorter than this
+const MIN_SCROBBLE_LENGTH: Duration = Duration::from_secs(30);
+
+/// A song counts as listened to after half of it, or this long, whichever comes first
+const SCROBBLE_AFTER: Duration = Duration::from_secs(240);
+
+/// Slack for the playhead running ahead of the wall clock while the sink buffers
+const BUFFER_SLACK: f32 = 0.5;
+
+/// A finished listen, ready to go into the play history
+#[derive(Debug, Clone)]
+pub struct Listen {
+    pub song_id: usize,
+    pub started_at: u64,    // Unix seconds
+    pub listened: f32,      // Seconds of the song actually heard
+    pub scrobbled: bool
+}
+
+/// Counts how much of the current song has actually been heard.
+/// Time spent paused and anything skipped over by a seek is not counted.
+pub struct ListenTracker {
+    song: Option<Song>,
+    started_at: u64,
+    listened: f32,
+    last_position: f32,
+    last_update: Instant,
+    scrobbled: bool
+}
+
+impl ListenTracker {
+    pub fn new() -> Self {
+        Self {
+            song: None,
+            started_at: 0,
+            listened: 0f32,
+            last_position: 0f32,
+            last_update: Instant::now(),
+            scrobbled: false
+        }
+    }
+
+    pub fn song_id(&self) -> Option<usize> {
+        self.song.as_ref().map(|song| song.id)
+    }
+
+    /// Start tracking a new song from `position`, handing back the listen for the previous one
+    pub fn start(&mut self, song: Option<Song>, position: f32) -> Option<Listen> {
+        let finished = self.finish();
+        self.song = song;
+        self.started_at = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0);
+        self.listened = 0f32;
+        self.last_position = position;
+        self.last_update = Instant::now();
+        self.scrobbled = false;
+        finished
+    }
+
+    /// Take the listen for the current song, if any of it was heard
+    pub fn finish(&mut self) -> Option<Listen> {
+        let song = self.song.take()?;
+        match self.listened > 0f32 {
+            true => Some(Listen {
+                song_id: song.id,
+                started_at: self.started_at,
+                listened: self.listened,
+                scrobbled: self.scrobbled
+            }),
+            false => None
+        }
+    }
+
+    /// Feed in the playhead. Only forward movement the wall clock can account for is counted,
+    /// so seeks and restores never count as listening.
+    pub fn update(&mut self, position: f32) {
+        let elapsed = self.last_update.elapsed().as_secs_f32();
+        let delta = position - self.last_position;
+
+        if delta > 0f32 && delta <= elapsed * MAX_SPEED + BUFFER_SLACK {
+            self.listened += delta;
+        }
+
+        self.last_position = position;
+        self.last_update = Instant::now();
+    }
+
+    /// The song that has just become eligible for scrobbling, at most once per play
+    pub fn scrobble(&mut self) -> Option<&Song> {
+        let song = self.song.as_ref()?;
+        let length = song.duration.as_secs_f32();
+        if self.scrobbled || length < MIN_SCROBBLE_LENGTH.as_secs_f32() {
+            return None;
+        }
+
+        match self.listened >= (length / 2f32).min(SCROBBLE_AFTER.as_secs_f32()) {
+            true => {
+                self.scrobbled = true;
+                Some(song)
+            }
+            false => None
+        }
+    }
+}
@@ -0,0 +1,148 @@
+use std::collections::VecDeque;
+use std::f32::consts::FRAC_PI_2;
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::time::Duration;
+
+use rodio::Source;
+use rodio::source::SeekError;
+
+use crate::backend::tempo::Playhead;
+
+/// Shortest section that can be looped, anything less is just a stutter
+pub const MIN_LOOP_LENGTH: f32 = 0.5;
+
+/// How many samples pass between checks of the playhead
+const CHECK_INTERVAL: usize = 64;
+
+/// How long the audio past the end marker is faded into the start marker, so the jump doesn't click
+const CROSSFADE_SECONDS: f32 = 0.02;
+
+/// A named A-B section saved against a song
+#[derive(Debug, Clone, PartialEq)]
+pub struct Section {
+    pub id: Option<usize>,
+    pub song_id: usize,
+    pub name: String,
+    pub start: f32,
+    pub end: f32
+}
+
+/// Shared between the audio thread and the `Looper` so markers can change mid-song
+#[derive(Clone, Default)]
+pub struct LoopControl {
+    section: Arc<Mutex<Option<(f32, f32)>>>
+}
+
+impl LoopControl {
+    /// Start and end in seconds of song time, `None` stops looping
+    pub fn set(&self, section: Option<(f32, f32)>) {
+        if let Ok(mut current) = self.section.lock() {
+            *current = section.filter(|(start, end)| end - start >= MIN_LOOP_LENGTH);
+        }
+    }
+
+    fn get(&self) -> Option<(f32, f32)> {
+        self.section.lock().ok().and_then(|section| *section)
+    }
+}
+
+/// Jumps back to the start marker whenever the playhead passes the end marker
+pub struct Looper<S> {
+    source: S,
+    control: LoopControl,
+    playhead: Playhead,
+    counter: usize,
+    channel: usize,
+
+    /// Read past the end marker before jumping, faded out over the start
+    tail: VecDeque<f32>,
+    fade: usize,
+    fade_len: usize
+}
+
+impl<S> Looper<S> where S: Source<Item = f32> {
+    pub fn new(source: S, control: LoopControl, playhead: Playhead) -> Self {
+        Self { source, control, playhead, counter: 0, channel: 0, tail: VecDeque::new(), fade: 0, fade_len: 0 }
+    }
+
+    /// Seek back to the start marker, reading ahead first when there is audio to crossfade from
+    fn jump(&mut self, start: f32, crossfade: bool) -> bool {
+        let channels = self.source.channels().max(1) as usize;
+        let samples = (self.source.sample_rate() as f32 * CROSSFADE_SECONDS) as usize * channels;
+        if crossfade {
+            self.tail = (0..samples).map_while(|_| self.source.next()).collect();
+        }
+
+        let seeked = self.source.try_seek(Duration::from_secs_f32(start)).is_ok();
+        self.fade = 0;
+        self.fade_len = if seeked { samples } else { 0 };
+        seeked
+    }
+
+    /// Equal power, and the same gain for every channel of a frame
+    fn crossfade(&mut self, sample: f32) -> f32 {
+        if self.fade >= self.fade_len { return sample; }
+
+        let channels = self.source.channels().max(1) as usize;
+        let angle = (self.fade / channels) as f32 / (self.fade_len / channels).max(1) as f32 * FRAC_PI_2;
+        self.fade += 1;
+
+        sample * angle.sin() + self.tail.pop_front().unwrap_or(0.0) * angle.cos()
+    }
+}
+
+impl<S> Iterator for Looper<S> where S: Source<Item = f32> {
+    type Item = f32;
+
+    fn next(&mut self) -> Option<f32> {
+        // Only jump between frames so the channels stay in order, and not while still fading
+        if self.counter >= CHECK_INTERVAL && self.channel == 0 && self.tail.is_empty() {
+            self.counter = 0;
+            if let Some((start, end)) = self.control.get() {
+                if self.playhead.seconds() >= end {
+                    self.jump(start, true);
+                }
+            }
+        }
+        self.counter += 1;
+        self.channel = (self.channel + 1) % self.source.channels().max(1) as usize;
+
+        // The seek failed, so what was read ahead plays as it was
+        if self.fade_len == 0 {
+            if let Some(sample) = self.tail.pop_front() { return Some(sample); }
+        }
+
+        let sample = match self.source.next() {
+            Some(sample) => sample,
+
+            // A section that runs to the very end of the song still loops, fading in as there is nothing left to fade out
+            None => match self.control.get() {
+                Some((start, _)) if self.tail.is_empty() && self.jump(start, false) => {
+                    self.counter = 1;
+                    self.channel = 1 % self.source.channels().max(1) as usize;
+                    self.source.next()?
+                }
+                _ => return None
+            }
+        };
+
+        Some(self.crossfade(sample))
+    }
+}
+
+impl<S> Source for Looper<S> where S: Source<Item = f32> {
+    fn current_frame_len(&self) -> Option<usize> { self.source.current_frame_len() }
+    fn channels(&self) -> u16 { self.source.channels() }
+    fn sample_rate(&self) -> u32 { self.source.sample_rate() }
+    fn total_duration(&self) -> Option<Duration> { None }
+
+    fn try_seek(&mut self, pos: Duration) -> Result<(), SeekError> {
+        self.counter = 0;
+        self.channel = 0;
+        self.tail.clear();
+        self.fade = 0;
+        self.fade_len = 0;
+        self.source.try_seek(pos)
+    }
+}
@@ -0,0 +1,276 @@
+use std::f64::consts::PI;
+use std::fs::File;
+use std::io::BufReader;
+use std::path::Path;
+use std::path::PathBuf;
+use std::thread::JoinHandle;
+use std::thread::spawn;
+
+use async_channel::Sender;
+use async_channel::Receiver;
+use async_channel::unbounded;
+
+use rodio::Decoder;
+use rodio::Source;
+
+use crate::backend::database_manager::DataLink;
+use crate::backend::database_interface::DatabaseInterface;
+use crate::backend::error::ResonateError;
+use crate::backend::music::Song;
+use crate::backend::music::NO_ALBUM;
+
+/// ReplayGain 2.0 reference level
+const TARGET_LUFS: f64 = -18.0;
+const ABSOLUTE_GATE: f64 = -70.0;
+const RELATIVE_GATE: f64 = -10.0;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Normalisation {
+    #[default]
+    Off,
+    Track,
+    Album
+}
+
+impl Normalisation {
+    pub fn from_string(string: &str) -> Option<Normalisation> {
+        match string.to_lowercase().as_str() {
+            "off" => Some(Normalisation::Off),
+            "track" => Some(Normalisation::Track),
+            "album" => Some(Normalisation::Album),
+            _ => None
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Normalisation::Off => "off",
+            Normalisation::Track => "track",
+            Normalisation::Album => "album"
+        }
+    }
+}
+
+/// Integrated loudness in LUFS and sample peak as a linear amplitude
+#[derive(Debug, Clone, Copy)]
+pub struct Loudness {
+    pub integrated: f64,
+    pub peak: f64
+}
+
+impl Loudness {
+    /// Combine the loudness of every track on an album by averaging their energy
+    pub fn combine(tracks: &[Loudness]) -> Option<Loudness> {
+        if tracks.is_empty() { return None; }
+
+        let energy = tracks.iter()
+            .map(|loudness| 10f64.powf(loudness.integrated / 10.0))
+            .sum::<f64>() / tracks.len() as f64;
+
+        Some(Loudness {
+            integrated: 10.0 * energy.log10(),
+            peak: tracks.iter().map(|loudness| loudness.peak).fold(0.0, f64::max)
+        })
+    }
+
+    /// Linear gain that brings this loudness to the target without clipping the peak
+    pub fn gain(&self) -> f32 {
+        let gain = 10f64.powf((TARGET_LUFS - self.integrated) / 20.0);
+        let gain = if self.peak > 0.0 { gain.min(1.0 / self.peak) } else { gain };
+        gain as f32
+    }
+}
+
+/// Look up the gain that should be applied to a song. Blocks on the database.
+pub fn blocking_gain(database: &DataLink, song: &Song, normalisation: Normalisation) -> f32 {
+    let track = match normalisation {
+        Normalisation::Off => return 1f32,
+        _ => DatabaseInterface::blocking_select_loudness(database.clone(), song.id)
+    };
+
+    let album = match (normalisation, song.album.as_ref()) {
+        (Normalisation::Album, Some(album)) if album != NO_ALBUM => Loudness::combine(
+            &DatabaseInterface::blocking_select_album_loudness(database.clone(), album.clone())
+        ),
+        _ => None
+    };
+
+    match album.or(track) {
+        Some(loudness) => loudness.gain(),
+        None => 1f32
+    }
+}
+
+/// Two cascaded biquads implementing the K-weighting curve from ITU-R BS.1770
+struct KWeighting {
+    b: [[f64; 3]; 2],
+    a: [[f64; 3]; 2],
+    state: Vec<[[f64; 2]; 2]>
+}
+
+impl KWeighting {
+    fn new(sample_rate: u32, channels: usize) -> Self {
+        let rate = sample_rate as f64;
+
+        let f0 = 1681.974450955533;
+        let g = 3.999843853973347;
+        let q = 0.7071752369554196;
+        let k = (PI * f0 / rate).tan();
+        let vh = 10f64.powf(g / 20.0);
+        let vb = vh.powf(0.4996667741545416);
+        let a0 = 1.0 + k / q + k * k;
+        let shelf_b = [(vh + vb * k / q + k * k) / a0, 2.0 * (k * k - vh) / a0, (vh - vb * k / q + k * k) / a0];
+        let shelf_a = [1.0, 2.0 * (k * k - 1.0) / a0, (1.0 - k / q + k * k) / a0];
+
+        let f0 = 38.13547087602444;
+        let q = 0.5003270373238773;
+        let k = (PI * f0 / rate).tan();
+        let a0 = 1.0 + k / q + k * k;
+        let highpass_b = [1.0, -2.0, 1.0];
+        let highpass_a = [1.0, 2.0 * (k * k - 1.0) / a0, (1.0 - k / q + k * k) / a0];
+
+        Self {
+            b: [shelf_b, highpass_b],
+            a: [shelf_a, highpass_a],
+            state: vec![[[0.0; 2]; 2]; channels]
+        }
+    }
+
+    fn process(&mut self, channel: usize, sample: f64) -> f64 {
+        let mut value = sample;
+        for stage in 0..2 {
+            let (b, a) = (self.b[stage], self.a[stage]);
+            let state = &mut self.state[channel][stage];
+            let output = b[0] * value + state[0];
+            state[0] = b[1] * value - a[1] * output + state[1];
+            state[1] = b[2] * value - a[2] * output;
+            value = output;
+        }
+        value
+    }
+}
+
+fn block_loudness(energy: f64) -> f64 {
+    -0.691 + 10.0 * energy.log10()
+}
+
+/// Decode an entire file and measure its integrated loudness (EBU R128) and sample peak
+pub fn analyse(path: &Path) -> Result<Loudness, ResonateError> {
+    let file = File::open(path).map_err(|_| ResonateError::DirectoryNotFound)?;
+    let decoder = Decoder::new(BufReader::new(file)).map_err(|_| ResonateError::AudioStreamError)?;
+
+    let channels = decoder.channels().max(1) as usize;
+    let sample_rate = decoder.sample_rate();
+    let mut filter = KWeighting::new(sample_rate, channels);
+
+    // Energy is accumulated in 100ms steps; a gating block is four consecutive steps
+    let step_frames = (sample_rate as usize / 10).max(1);
+    let mut steps: Vec<f64> = Vec::new();
+    let mut step_energy = 0f64;
+    let mut step_count = 0usize;
+    let mut peak = 0f64;
+
+    let mut channel = 0usize;
+    for sample in decoder {
+        let sample = sample as f64 / i16::MAX as f64;
+        peak = peak.max(sample.abs());
+
+        let weighted = filter.process(channel, sample);
+        step_energy += weighted * weighted;
+
+        channel += 1;
+        if channel == channels {
+            channel = 0;
+            step_count += 1;
+            if step_count == step_frames {
+                steps.push(step_energy / step_frames as f64);
+                step_energy = 0.0;
+                step_count = 0;
+            }
+        }
+    }
+
+    let blocks: Vec<f64> = steps.windows(4)
+        .map(|window| window.iter().sum::<f64>() / 4.0)
+        .filter(|energy| block_loudness(*energy) > ABSOLUTE_GATE)
+        .collect();
+
+    if blocks.is_empty() {
+        return Ok(Loudness { integrated: ABSOLUTE_GATE, peak });
+    }
+
+    let relative_gate = block_loudness(blocks.iter().sum::<f64>() / blocks.len() as f64) + RELATIVE_GATE;
+    let gated: Vec<f64> = blocks.into_iter().filter(|energy| block_loudness(*energy) > relative_gate).collect();
+
+    let integrated = match gated.is_empty() {
+        true => relative_gate,
+        false => block_loudness(gated.iter().sum::<f64>() / gated.len() as f64)
+    };
+
+    Ok(Loudness { integrated, peak })
+}
+
+enum LoudnessTask {
+    Analyse(Song),
+    Backfill
+}
+
+/// Background job that measures the loudness of downloaded songs and stores it in the database
+pub struct LoudnessAnalyser {
+    _handle: JoinHandle<()>,
+    sender: Sender<LoudnessTask>
+}
+
+impl LoudnessAnalyser {
+    pub fn new(database: DataLink, music_path: PathBuf) -> Self {
+        let (sender, receiver) = unbounded();
+        let thread_sender = sender.clone();
+        Self {
+            _handle: spawn(move || Self::run(database, music_path, thread_sender, receiver)),
+            sender
+        }
+    }
+
+    /// Queue a single song for analysis
+    pub fn send(&self, song: Song) {
+        let _ = self.sender.send_blocking(LoudnessTask::Analyse(song));
+    }
+
+    /// Queue every downloaded song that has not been analysed yet
+    pub fn backfill(&self) {
+        let _ = self.sender.send_blocking(LoudnessTask::Backfill);
+    }
+
+    fn run(database: DataLink, music_path: PathBuf, sender: Sender<LoudnessTask>, receiver: Receiver<LoudnessTask>) {
+        while let Ok(task) = receiver.recv_blocking() {
+            match task {
+                LoudnessTask::Backfill => {
+                    for song in DatabaseInterface::blocking_select_songs_without_loudness(
+                        database.clone(), music_path.clone()
+                    ) {
+                        let _ = sender.send_blocking(LoudnessTask::Analyse(song));
+                    }
+                }
+
+                LoudnessTask::Analyse(song) => {
+                    let path = match song.music_path.as_ref() {
+                        Some(path) => path,
+                        None => continue
+                    };
+
+                    if DatabaseInterface::blocking_select_loudness(database.clone(), song.id).is_some() {
+                        continue;
+                    }
+
+                    match analyse(path) {
+                        Ok(loudness) => {
+                            println!("[LOUDNESS] {} measured at {:.1} LUFS", song.title, loudness.integrated);
+                            DatabaseInterface::insert_loudness(database.clone(), song.id, loudness);
+                        }
+                        Err(e) => println!("[LOUDNESS] Failed to analyse {}: {e:?}", song.title)
+                    }
+                }
+            }
+        }
+    }
+}
@@ -12,4 +12,16 @@ pub mod database_manager;
 pub mod mediacontrol;
 pub mod lyrics;
 pub mod thumbnail;
+pub mod loudness;
+pub mod equalizer;
+pub mod tempo;
+pub mod output;
+pub mod radio;
+pub mod listening;
+pub mod visualiser;
+pub mod waveform;
+pub mod looper;
+pub mod downloads;
+pub mod tools;
+pub mod tagging;
 mod sql;
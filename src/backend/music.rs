@@ -1,7 +1,54 @@
 use std::time::Duration;
 use std::fmt::Formatter;
+use std::path::Path;
 use std::path::PathBuf;
 
+/// Every extension a downloaded song can have that the decoder can play, in the order they are looked for
+pub const PLAYABLE_EXTENSIONS: [&str; 5] = ["m4a", "mp3", "flac", "ogg", "wav"];
+
+/// Stored in the album column for songs that don't belong to one
+pub const NO_ALBUM: &str = "none";
+
+/// What yt-dlp is asked to save downloads as.
+/// There is no Opus: symphonia can't decode it, and libopus bindings would need a native build.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum AudioFormat {
+    /// The AAC stream YouTube serves, remuxed without re-encoding when available
+    #[default]
+    M4a,
+    Mp3,
+    Flac
+}
+
+impl AudioFormat {
+    pub const ALL: [AudioFormat; 3] = [AudioFormat::M4a, AudioFormat::Mp3, AudioFormat::Flac];
+
+    pub fn from_string(string: &str) -> Option<AudioFormat> {
+        match string.to_lowercase().as_str() {
+            "m4a" => Some(AudioFormat::M4a),
+            "mp3" => Some(AudioFormat::Mp3),
+            "flac" => Some(AudioFormat::Flac),
+            _ => None
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            AudioFormat::M4a => "m4a",
+            AudioFormat::Mp3 => "mp3",
+            AudioFormat::Flac => "flac"
+        }
+    }
+
+    /// yt-dlp format selection, preferring a stream that needs no conversion
+    pub fn selector(&self) -> &'static str {
+        match self {
+            AudioFormat::M4a => "bestaudio[ext=m4a]/bestaudio",
+            AudioFormat::Mp3 | AudioFormat::Flac => "bestaudio"
+        }
+    }
+}
+
 #[derive(Debug, Clone, Hash, PartialEq, Eq)]
 pub struct Song {
     // Stored in database
@@ -16,16 +63,27 @@ pub struct Song {
 
 impl Song {
     pub fn new(
-        id: usize, yt_id: String, title: String, artist: String, album: Option<String>, duration: Duration, music_dir: PathBuf
+        id: usize, yt_id: String, title: String, artist: String, album: Option<String>, duration: Duration, music_path: Option<PathBuf>
     ) -> Self {
-        let mut s = Self { id, yt_id, title, artist, album, duration, music_path: None };
-        s.load_music_path(music_dir);
-        s
+        Self { id, yt_id, title, artist, album, duration, music_path }
+    }
+
+    /// Where a song downloaded with this extension lives
+    pub fn file_path(music_dir: &Path, yt_id: &str, extension: &str) -> PathBuf {
+        music_dir.join(format!("{yt_id}.{extension}"))
+    }
+
+    /// Extension of the downloaded file, as stored in the database
+    pub fn extension(&self) -> Option<String> {
+        self.music_path.as_ref()?.extension().map(|extension| extension.to_string_lossy().to_lowercase())
     }
 
+    /// Find the downloaded file, whichever format it was saved in.
+    /// Only needed straight after a download; otherwise the database knows the extension.
     pub fn load_music_path(&mut self, music_dir: PathBuf) {
-        let music_path = music_dir.join(format!("{}.mp3", self.yt_id));
-        self.music_path = if music_path.exists() { Some(music_path) } else { None }
+        self.music_path = PLAYABLE_EXTENSIONS.iter()
+            .map(|extension| Self::file_path(&music_dir, &self.yt_id, extension))
+            .find(|music_path| music_path.exists());
     }
 
     pub fn display_duration(&self) -> String {
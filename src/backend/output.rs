@@ -0,0 +1,226 @@
+use std::fs::File;
+use std::io::BufWriter;
+use std::io::Seek;
+use std::io::SeekFrom;
+use std::io::Write;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::sync::atomic::AtomicBool;
+use std::sync::atomic::Ordering;
+use std::thread::JoinHandle;
+use std::thread::sleep;
+use std::thread::spawn;
+use std::time::Duration;
+use std::time::Instant;
+
+use rodio::OutputStream;
+use rodio::OutputStreamHandle;
+use rodio::Sink;
+use rodio::queue::SourcesQueueOutput;
+use rodio::source::UniformSourceIterator;
+
+use crate::backend::error::ResonateError;
+
+/// Overrides the configured backend, mainly so CI can run without touching the config
+const OUTPUT_ENV: &str = "RESONATE_OUTPUT";
+
+/// Format every headless backend renders to
+const CHANNELS: u16 = 2;
+const SAMPLE_RATE: u32 = 44100;
+
+/// How much audio a headless backend pulls before sleeping
+const CHUNK: Duration = Duration::from_millis(20);
+
+/// How often the WAV header is brought up to date, so an unclean exit still leaves a readable file
+const HEADER_INTERVAL: Duration = Duration::from_secs(1);
+
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub enum OutputBackend {
+    #[default]
+    Device,
+    Null,
+    File(PathBuf)
+}
+
+impl OutputBackend {
+    /// `device`, `null` or `file:<path to .wav>`
+    pub fn from_string(string: &str) -> Option<OutputBackend> {
+        match string.trim().split_once(':') {
+            Some((kind, path)) if kind.eq_ignore_ascii_case("file") && !path.trim().is_empty() =>
+                Some(OutputBackend::File(PathBuf::from(path.trim()))),
+            _ => match string.trim().to_lowercase().as_str() {
+                "device" => Some(OutputBackend::Device),
+                "null" => Some(OutputBackend::Null),
+                _ => None
+            }
+        }
+    }
+
+    pub fn to_config_string(&self) -> String {
+        match self {
+            OutputBackend::Device => String::from("device"),
+            OutputBackend::Null => String::from("null"),
+            OutputBackend::File(path) => format!("file:{}", path.to_string_lossy())
+        }
+    }
+
+    /// The environment variable wins over the configured backend if it is set and valid
+    pub fn resolve(configured: &OutputBackend) -> OutputBackend {
+        match std::env::var(OUTPUT_ENV).ok().and_then(|value| OutputBackend::from_string(&value)) {
+            Some(backend) => backend,
+            None => configured.clone()
+        }
+    }
+}
+
+/// Keeps whatever is producing sound alive for as long as the player exists
+pub enum Output {
+    Device {
+        _stream: OutputStream,
+        _handle: OutputStreamHandle
+    },
+    Headless {
+        running: Arc<AtomicBool>,
+        _thread: JoinHandle<()>
+    }
+}
+
+impl Output {
+    /// Open the backend and a sink that plays through it. A missing sound device falls back to
+    /// the null backend so the queue keeps working.
+    pub fn open(backend: OutputBackend) -> Result<(Output, Sink), ResonateError> {
+        match backend {
+            OutputBackend::Device => match Self::device() {
+                Ok(output) => Ok(output),
+                Err(_) => {
+                    println!("[AUDIO] No output device available, falling back to the null sink");
+                    Ok(Self::headless(None))
+                }
+            },
+            OutputBackend::Null => Ok(Self::headless(None)),
+            OutputBackend::File(path) => Ok(Self::headless(Some(WavWriter::create(path)?)))
+        }
+    }
+
+    fn device() -> Result<(Output, Sink), ResonateError> {
+        let (_stream, handle) = match OutputStream::try_default() {
+            Ok(data) => data,
+            Err(_) => return Err(ResonateError::AudioStreamError)
+        };
+
+        let sink = match Sink::try_new(&handle) {
+            Ok(sink) => sink,
+            Err(_) => return Err(ResonateError::AudioStreamError)
+        };
+
+        Ok((Output::Device { _stream, _handle: handle }, sink))
+    }
+
+    fn headless(writer: Option<WavWriter>) -> (Output, Sink) {
+        let (sink, queue) = Sink::new_idle();
+        let running = Arc::new(AtomicBool::new(true));
+        let thread_running = running.clone();
+        let _thread = spawn(move || drain(queue, writer, thread_running));
+        (Output::Headless { running, _thread }, sink)
+    }
+}
+
+impl Drop for Output {
+    fn drop(&mut self) {
+        if let Output::Headless { running, .. } = self {
+            running.store(false, Ordering::Relaxed);
+        }
+    }
+}
+
+/// Pull samples out of the sink at the rate a real device would, optionally recording them
+fn drain(queue: SourcesQueueOutput<f32>, mut writer: Option<WavWriter>, running: Arc<AtomicBool>) {
+    let mut source = UniformSourceIterator::<_, f32>::new(queue, CHANNELS, SAMPLE_RATE);
+    let chunk = (SAMPLE_RATE as f64 * CHUNK.as_secs_f64()) as usize * CHANNELS as usize;
+    let samples_per_second = SAMPLE_RATE as f64 * CHANNELS as f64;
+
+    let start = Instant::now();
+    let mut last_header = start;
+    let mut pulled = 0u64;
+
+    while running.load(Ordering::Relaxed) {
+        for _ in 0..chunk {
+            let sample = source.next().unwrap_or(0f32);
+            if let Some(writer) = writer.as_mut() {
+                writer.write(sample);
+            }
+        }
+        pulled += chunk as u64;
+
+        if let Some(writer) = writer.as_mut() {
+            if last_header.elapsed() >= HEADER_INTERVAL {
+                writer.update_header();
+                last_header = Instant::now();
+            }
+        }
+
+        let due = start + Duration::from_secs_f64(pulled as f64 / samples_per_second);
+        if let Some(wait) = due.checked_duration_since(Instant::now()) {
+            sleep(wait);
+        }
+    }
+
+    if let Some(writer) = writer.as_mut() {
+        writer.update_header();
+    }
+}
+
+/// Minimal 16 bit PCM WAV writer
+struct WavWriter {
+    file: BufWriter<File>,
+    samples: u32
+}
+
+impl WavWriter {
+    const HEADER_LEN: u32 = 44;
+
+    fn create(path: PathBuf) -> Result<Self, ResonateError> {
+        let file = File::create(&path).map_err(|_| ResonateError::DirectoryNotFound)?;
+        let mut writer = Self { file: BufWriter::new(file), samples: 0 };
+        writer.write_header().map_err(|_| ResonateError::AudioStreamError)?;
+        println!("[AUDIO] Writing output to {}", path.to_string_lossy());
+        Ok(writer)
+    }
+
+    fn write_header(&mut self) -> std::io::Result<()> {
+        let data_len = self.samples.saturating_mul(2);
+        let block_align = CHANNELS * 2;
+
+        self.file.write_all(b"RIFF")?;
+        self.file.write_all(&(Self::HEADER_LEN - 8).saturating_add(data_len).to_le_bytes())?;
+        self.file.write_all(b"WAVEfmt ")?;
+        self.file.write_all(&16u32.to_le_bytes())?;
+        self.file.write_all(&1u16.to_le_bytes())?;
+        self.file.write_all(&CHANNELS.to_le_bytes())?;
+        self.file.write_all(&SAMPLE_RATE.to_le_bytes())?;
+        self.file.write_all(&(SAMPLE_RATE * block_align as u32).to_le_bytes())?;
+        self.file.write_all(&block_align.to_le_bytes())?;
+        self.file.write_all(&16u16.to_le_bytes())?;
+        self.file.write_all(b"data")?;
+        self.file.write_all(&data_len.to_le_bytes())
+    }
+
+    fn write(&mut self, sample: f32) {
+        let value = (sample.clamp(-1.0, 1.0) * i16::MAX as f32) as i16;
+        if self.file.write_all(&value.to_le_bytes()).is_ok() {
+            self.samples = self.samples.saturating_add(1);
+        }
+    }
+
+    /// Rewrite the sizes in the header to cover everything written so far
+    fn update_header(&mut self) {
+        let result = self.file.seek(SeekFrom::Start(0))
+            .and_then(|_| self.write_header())
+            .and_then(|_| self.file.seek(SeekFrom::End(0)))
+            .and_then(|_| self.file.flush());
+
+        if result.is_err() {
+            println!("[AUDIO] Failed to update WAV header");
+        }
+    }
+}
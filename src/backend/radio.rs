@@ -0,0 +1,155 @@
+use std::collections::HashMap;
+use std::collections::HashSet;
+use std::collections::VecDeque;
+use std::path::Path;
+use std::path::PathBuf;
+use std::thread::JoinHandle;
+use std::thread::spawn;
+
+use async_channel::Sender;
+use async_channel::Receiver;
+use async_channel::unbounded;
+
+use rand::Rng;
+
+use crate::backend::music::Song;
+use crate::backend::music::NO_ALBUM;
+use crate::backend::database_manager::DataLink;
+use crate::backend::database_interface::DatabaseInterface;
+
+/// Songs played this recently are never picked, even if the queue they were in has been replaced
+const RECENT_WINDOW: usize = 50;
+
+/// How many of the most recent songs in the queue a pick is based on
+const SEEDS: usize = 5;
+
+/// Only the best few candidates are drawn from, weighted by how similar they are
+const CANDIDATES: usize = 10;
+
+const ARTIST_WEIGHT: f32 = 3.0;
+const ALBUM_WEIGHT: f32 = 2.0;
+const PLAYLIST_WEIGHT: f32 = 1.0;
+
+struct RadioRequest {
+    generation: usize,
+    history: Vec<Song>,
+    recent: Vec<usize>
+}
+
+/// Picks songs from the local library to keep playing once the queue runs out.
+/// Picking scans the whole library, so it happens on its own thread while the last song plays.
+pub struct Radio {
+    _handle: JoinHandle<()>,
+    sender: Sender<RadioRequest>,
+    recent: VecDeque<usize>,
+    requested: Option<usize>
+}
+
+impl Radio {
+    /// `picked` is handed each pick along with the generation it was asked for
+    pub fn new(database: DataLink, music_path: PathBuf, picked: impl Fn(usize, Song) + Send + 'static) -> Self {
+        let (sender, receiver) = unbounded();
+        Self {
+            _handle: spawn(move || Self::run(database, music_path, receiver, picked)),
+            sender,
+            recent: VecDeque::new(),
+            requested: None
+        }
+    }
+
+    /// Remember a song that started playing
+    pub fn played(&mut self, song_id: usize) {
+        if self.recent.back() == Some(&song_id) { return; }
+        self.recent.push_back(song_id);
+        if self.recent.len() > RECENT_WINDOW {
+            self.recent.pop_front();
+        }
+    }
+
+    /// Ask for a song to follow the one loaded as `generation`, once per generation
+    pub fn request<'a>(&mut self, generation: usize, history: impl Iterator<Item = &'a Song>) {
+        if self.requested == Some(generation) { return; }
+        self.requested = Some(generation);
+        let _ = self.sender.send_blocking(RadioRequest {
+            generation,
+            history: history.cloned().collect(),
+            recent: self.recent.iter().copied().collect()
+        });
+    }
+
+    fn run(database: DataLink, music_path: PathBuf, receiver: Receiver<RadioRequest>, picked: impl Fn(usize, Song)) {
+        while let Ok(mut request) = receiver.recv_blocking() {
+            // Only the newest request can still be used
+            while let Ok(newer) = receiver.try_recv() {
+                request = newer;
+            }
+
+            if let Some(song) = blocking_next(&database, &music_path, &request.history, &request.recent) {
+                picked(request.generation, song);
+            }
+        }
+    }
+}
+
+/// Pick a downloaded song similar to the end of `history`. Nothing in `history` or `recent` is picked again.
+/// Blocks on the database.
+fn blocking_next(database: &DataLink, music_path: &Path, history: &[Song], recent: &[usize]) -> Option<Song> {
+    let seeds = &history[history.len().saturating_sub(SEEDS)..];
+    let played: HashSet<usize> = history.iter().map(|song| song.id).chain(recent.iter().copied()).collect();
+
+    let mut playlists: HashMap<usize, HashSet<usize>> = HashMap::new();
+    for (playlist_id, song_id) in DatabaseInterface::blocking_select_all_entries(database.clone()) {
+        playlists.entry(song_id).or_default().insert(playlist_id);
+    }
+
+    // Later seeds were played more recently so count for more
+    let mut scored: Vec<(f32, Song)> = DatabaseInterface::blocking_select_all_songs(
+        database.clone(), music_path.to_path_buf()
+    ).into_iter()
+        .filter(|song| song.music_path.is_some())
+        .filter(|song| !played.contains(&song.id))
+        .map(|song| (
+            seeds.iter().enumerate()
+                .map(|(idx, seed)| similarity(seed, &song, &playlists) * (idx + 1) as f32)
+                .sum(),
+            song
+        ))
+        .collect();
+
+    if scored.is_empty() { return None; }
+
+    scored.sort_by(|a, b| b.0.total_cmp(&a.0));
+    scored.truncate(CANDIDATES);
+
+    // Everything gets a little weight so an unrelated library still plays something
+    let total: f32 = scored.iter().map(|(score, _)| score + 1.0).sum();
+    let mut pick = rand::rng().random_range(0.0..total);
+    let idx = scored.iter().position(|(score, _)| {
+        pick -= score + 1.0;
+        pick < 0.0
+    }).unwrap_or(0);
+
+    Some(scored.swap_remove(idx).1)
+}
+
+/// How alike two songs are, from a shared artist, a shared album and how many playlists hold both.
+/// Tags would be another signal, but songs have none stored to compare.
+fn similarity(seed: &Song, candidate: &Song, playlists: &HashMap<usize, HashSet<usize>>) -> f32 {
+    let mut score = 0f32;
+
+    if seed.artist.eq_ignore_ascii_case(&candidate.artist) {
+        score += ARTIST_WEIGHT;
+    }
+
+    if let (Some(a), Some(b)) = (seed.album.as_ref(), candidate.album.as_ref()) {
+        if a != NO_ALBUM && a == b {
+            score += ALBUM_WEIGHT;
+        }
+    }
+
+    if let (Some(a), Some(b)) = (playlists.get(&seed.id), playlists.get(&candidate.id)) {
+        score += a.intersection(b).count() as f32 * PLAYLIST_WEIGHT;
+    }
+
+    score
+}
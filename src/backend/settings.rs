@@ -1,11 +1,25 @@
 #![allow(dead_code)]
 use std::default::Default;
 use std::path::Path;
+use std::path::PathBuf;
 use std::fs::read_to_string;
+use std::fs::write;
 
 use iced::Color;
 
 use crate::frontend::widgets::ResonateColour;
+use crate::backend::loudness::Normalisation;
+use crate::backend::equalizer::EqualizerPreset;
+use crate::backend::equalizer::Gains;
+use crate::backend::equalizer::BANDS;
+use crate::backend::tempo::MIN_SPEED;
+use crate::backend::tempo::MAX_SPEED;
+use crate::backend::output::OutputBackend;
+use crate::backend::audio::QueueStartup;
+use crate::backend::music::AudioFormat;
+
+const MIN_PROGRESS_INTERVAL: u64 = 16;
+const MAX_PROGRESS_INTERVAL: u64 = 5000;
 
 #[allow(clippy::enum_variant_names)]
 #[derive(Clone, Debug)]
@@ -17,15 +31,39 @@ pub enum Secret {
     FMSession(String),
 }
 
-
+#[derive(Clone, Debug)]
 pub struct Settings {
     pub colour: Color,
-    pub max_download_concurrency: usize
+    pub max_download_concurrency: usize,
+    pub normalisation: Normalisation,
+    pub equalizer: Gains,
+    pub speed: f32,
+    pub preserve_pitch: bool,
+    pub output: OutputBackend,
+    pub queue_startup: QueueStartup,
+    pub progress_interval: u64,          // Milliseconds between progress updates while playing
+    pub autoplay: bool,                  // Keep playing similar songs from the library when the queue ends
+    pub download_format: AudioFormat,
+    pub dlp_version: Option<String>,     // yt-dlp version or channel to stay on, latest release if None
+    pub dlp_path: Option<PathBuf>,       // Use this yt-dlp instead of the bundled one or PATH
+    pub ffmpeg_path: Option<PathBuf>
 }
 
 enum Setting {
     Colour,
-    MaxDownloadConcurrency
+    MaxDownloadConcurrency,
+    Normalisation,
+    Equalizer,
+    Speed,
+    PreservePitch,
+    Output,
+    QueueStartup,
+    ProgressInterval,
+    Autoplay,
+    DownloadFormat,
+    DLPVersion,
+    DLPPath,
+    FfmpegPath
 }
 
 impl Setting {
@@ -33,6 +71,18 @@ impl Setting {
         match string.to_lowercase().as_str() {
             "colour" => Some(Setting::Colour),
             "max_download_concurrency" => Some(Setting::MaxDownloadConcurrency),
+            "normalisation" => Some(Setting::Normalisation),
+            "equalizer" => Some(Setting::Equalizer),
+            "speed" => Some(Setting::Speed),
+            "preserve_pitch" => Some(Setting::PreservePitch),
+            "output" => Some(Setting::Output),
+            "queue_startup" => Some(Setting::QueueStartup),
+            "progress_interval" => Some(Setting::ProgressInterval),
+            "autoplay" => Some(Setting::Autoplay),
+            "download_format" => Some(Setting::DownloadFormat),
+            "dlp_version" => Some(Setting::DLPVersion),
+            "dlp_path" => Some(Setting::DLPPath),
+            "ffmpeg_path" => Some(Setting::FfmpegPath),
             _ => None
         }
     }
@@ -79,6 +129,42 @@ impl Settings {
                     Setting::MaxDownloadConcurrency => if let Ok(value) = line.value.parse::<usize>() {
                         settings.max_download_concurrency = value
                     }
+                    Setting::Normalisation => if let Some(value) = Normalisation::from_string(&line.value) {
+                        settings.normalisation = value
+                    }
+                    Setting::Equalizer => if let Some(value) = EqualizerPreset::parse_gains(&line.value) {
+                        settings.equalizer = value
+                    }
+                    Setting::Speed => if let Ok(value) = line.value.parse::<f32>() {
+                        settings.speed = value.clamp(MIN_SPEED, MAX_SPEED)
+                    }
+                    Setting::PreservePitch => if let Ok(value) = line.value.parse::<bool>() {
+                        settings.preserve_pitch = value
+                    }
+                    Setting::Output => if let Some(value) = OutputBackend::from_string(&line.value) {
+                        settings.output = value
+                    }
+                    Setting::QueueStartup => if let Some(value) = QueueStartup::from_string(&line.value) {
+                        settings.queue_startup = value
+                    }
+                    Setting::ProgressInterval => if let Ok(value) = line.value.parse::<u64>() {
+                        settings.progress_interval = value.clamp(MIN_PROGRESS_INTERVAL, MAX_PROGRESS_INTERVAL)
+                    }
+                    Setting::Autoplay => if let Ok(value) = line.value.parse::<bool>() {
+                        settings.autoplay = value
+                    }
+                    Setting::DownloadFormat => if let Some(value) = AudioFormat::from_string(&line.value) {
+                        settings.download_format = value
+                    }
+                    Setting::DLPVersion => if !line.value.is_empty() {
+                        settings.dlp_version = Some(line.value)
+                    }
+                    Setting::DLPPath => if !line.value.is_empty() {
+                        settings.dlp_path = Some(PathBuf::from(line.value))
+                    }
+                    Setting::FfmpegPath => if !line.value.is_empty() {
+                        settings.ffmpeg_path = Some(PathBuf::from(line.value))
+                    }
                 }
             );
 
@@ -87,13 +173,50 @@ impl Settings {
             Settings::default()
         }
     }
+
+    /// Write every setting back to the config file
+    pub fn save(&self, directory: &Path) {
+        let [r, g, b, _] = self.colour.into_rgba8();
+        let contents = [
+            format!("colour = #{r:02x}{g:02x}{b:02x}"),
+            format!("max_download_concurrency = {}", self.max_download_concurrency),
+            format!("normalisation = {}", self.normalisation.as_str()),
+            format!("equalizer = {}", EqualizerPreset::serialise_gains(&self.equalizer)),
+            format!("speed = {:.2}", self.speed),
+            format!("preserve_pitch = {}", self.preserve_pitch),
+            format!("output = {}", self.output.to_config_string()),
+            format!("queue_startup = {}", self.queue_startup.as_str()),
+            format!("progress_interval = {}", self.progress_interval),
+            format!("autoplay = {}", self.autoplay),
+            format!("download_format = {}", self.download_format.as_str()),
+            format!("dlp_version = {}", self.dlp_version.as_deref().unwrap_or("")),
+            format!("dlp_path = {}", self.dlp_path.as_ref().map(|path| path.to_string_lossy()).unwrap_or_default()),
+            format!("ffmpeg_path = {}", self.ffmpeg_path.as_ref().map(|path| path.to_string_lossy()).unwrap_or_default())
+        ].join("\n");
+
+        if write(directory.join(".conf"), contents).is_err() {
+            println!("[SETTINGS] Failed to write config");
+        }
+    }
 }
 
 impl Default for Settings {
     fn default() -> Settings {
         Settings {
             colour: Color::from_rgb8(255, 0, 0),
-            max_download_concurrency: 4
+            max_download_concurrency: 4,
+            normalisation: Normalisation::Off,
+            equalizer: [0f32; BANDS],
+            speed: 1f32,
+            preserve_pitch: true,
+            output: OutputBackend::Device,
+            queue_startup: QueueStartup::Restore,
+            progress_interval: 200,
+            autoplay: false,
+            download_format: AudioFormat::M4a,
+            dlp_version: None,
+            dlp_path: None,
+            ffmpeg_path: None
         }
     }
 }
@@ -5,7 +5,8 @@ pub const CREATE_SONG_TABLE: &str = "
         title TEXT NOT NULL,
         artist TEXT NOT NULL,
         album TEXT NOT NULL,
-        duration INTEGER NOT NULL
+        duration INTEGER NOT NULL,
+        extension TEXT
     );
 ";
 
@@ -36,9 +37,16 @@ pub const CREATE_SECRETS_TABLE: &str = "
 
 pub const INSERT_SONG: &str = "
     INSERT INTO Songs
-    VALUES(null, ?, ?, ?, ?, ?)
+    VALUES(null, ?, ?, ?, ?, ?, null)
 ";
 
+pub const UPDATE_SONG_EXTENSION: &str = "UPDATE Songs SET extension = ? WHERE id = ?";
+pub const UPDATE_SONG_EXTENSION_BY_YOUTUBE_ID: &str = "UPDATE Songs SET extension = ? WHERE yt_id = ?";
+
+// Libraries from before the extension was stored need the column added and filled in once
+pub const COUNT_SONG_EXTENSION_COLUMN: &str = "SELECT COUNT(*) FROM pragma_table_info('Songs') WHERE name = 'extension'";
+pub const ADD_SONG_EXTENSION_COLUMN: &str = "ALTER TABLE Songs ADD COLUMN extension TEXT";
+
 pub const INSERT_PLAYLIST: &str = "
     INSERT INTO Playlists
     VALUES(null, ?)
@@ -71,3 +79,147 @@ pub const SELECT_ALL_PLAYLISTS: &str = "SELECT * FROM Playlists";
 pub const SELECT_SONG_BY_TITLE: &str = "SELECT * FROM Songs WHERE title = ?";
 pub const SELECT_SECRET_BY_NAME: &str = "SELECT * FROM Secrets WHERE name = ?";
 pub const REMOVE_SECRET_BY_NAME: &str = "DELETE FROM Secrets WHERE name = ?";
+
+pub const CREATE_LOUDNESS_TABLE: &str = "
+    CREATE TABLE IF NOT EXISTS Loudness (
+        song_id INTEGER PRIMARY KEY,
+        integrated REAL NOT NULL,
+        peak REAL NOT NULL,
+        FOREIGN KEY (song_id) REFERENCES Songs(id) ON DELETE CASCADE
+    );
+";
+
+pub const INSERT_LOUDNESS: &str = "
+    INSERT OR REPLACE INTO Loudness
+    VALUES(?, ?, ?)
+";
+
+pub const SELECT_ALBUM_LOUDNESS: &str = "
+    SELECT Loudness.* FROM Loudness
+    INNER JOIN Songs ON Songs.id = Loudness.song_id
+    WHERE Songs.album = ?;
+";
+
+pub const SELECT_SONGS_WITHOUT_LOUDNESS: &str = "
+    SELECT Songs.* FROM Songs
+    LEFT JOIN Loudness ON Songs.id = Loudness.song_id
+    WHERE Loudness.song_id IS NULL;
+";
+
+pub const SELECT_LOUDNESS_BY_SONG_ID: &str = "SELECT * FROM Loudness WHERE song_id = ?";
+
+pub const CREATE_EQUALIZER_PRESETS_TABLE: &str = "
+    CREATE TABLE IF NOT EXISTS EqualizerPresets (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        name TEXT NOT NULL,
+        gains TEXT NOT NULL
+    );
+";
+
+pub const INSERT_EQUALIZER_PRESET: &str = "
+    INSERT INTO EqualizerPresets
+    VALUES(null, ?, ?)
+";
+
+pub const SELECT_ALL_EQUALIZER_PRESETS: &str = "SELECT * FROM EqualizerPresets";
+pub const REMOVE_EQUALIZER_PRESET: &str = "DELETE FROM EqualizerPresets WHERE id = ?";
+
+pub const CREATE_QUEUE_TABLE: &str = "
+    CREATE TABLE IF NOT EXISTS Queue (
+        id INTEGER PRIMARY KEY,
+        position INTEGER NOT NULL,
+        repeat INTEGER NOT NULL,
+        shuffle INTEGER NOT NULL,
+        offset REAL NOT NULL
+    );
+";
+
+pub const CREATE_QUEUE_ENTRIES_TABLE: &str = "
+    CREATE TABLE IF NOT EXISTS QueueEntries (
+        position INTEGER PRIMARY KEY,
+        song_id INTEGER NOT NULL,
+        FOREIGN KEY (song_id) REFERENCES Songs(id) ON DELETE CASCADE
+    );
+";
+
+pub const INSERT_QUEUE: &str = "
+    INSERT OR REPLACE INTO Queue
+    VALUES(0, ?, ?, ?, ?)
+";
+
+pub const REMOVE_ALL_QUEUE_ENTRIES: &str = "DELETE FROM QueueEntries";
+
+// Takes a JSON array of song ids, so the whole queue is written in one statement
+pub const INSERT_QUEUE_ENTRIES: &str = "
+    INSERT INTO QueueEntries
+    SELECT key, value FROM json_each(?)
+";
+
+pub const SELECT_QUEUE: &str = "SELECT * FROM Queue WHERE id = 0";
+pub const SELECT_QUEUE_SONGS: &str = "
+    SELECT QueueEntries.position, Songs.* FROM QueueEntries
+    INNER JOIN Songs ON Songs.id = QueueEntries.song_id
+    ORDER BY QueueEntries.position
+";
+pub const SELECT_ALL_ENTRIES: &str = "SELECT * FROM Entries";
+
+pub const CREATE_HISTORY_TABLE: &str = "
+    CREATE TABLE IF NOT EXISTS History (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        song_id INTEGER NOT NULL,
+        started_at INTEGER NOT NULL,
+        listened REAL NOT NULL,
+        scrobbled INTEGER NOT NULL,
+        FOREIGN KEY (song_id) REFERENCES Songs(id) ON DELETE CASCADE
+    );
+";
+
+pub const INSERT_HISTORY: &str = "
+    INSERT INTO History
+    VALUES(null, ?, ?, ?, ?)
+";
+
+pub const CREATE_SECTIONS_TABLE: &str = "
+    CREATE TABLE IF NOT EXISTS Sections (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        song_id INTEGER NOT NULL,
+        name TEXT NOT NULL,
+        start REAL NOT NULL,
+        end REAL NOT NULL,
+        FOREIGN KEY (song_id) REFERENCES Songs(id) ON DELETE CASCADE
+    );
+";
+
+pub const INSERT_SECTION: &str = "
+    INSERT INTO Sections
+    VALUES(null, ?, ?, ?, ?)
+";
+
+pub const SELECT_SECTIONS_BY_SONG_ID: &str = "SELECT * FROM Sections WHERE song_id = ? ORDER BY start";
+pub const REMOVE_SECTION: &str = "DELETE FROM Sections WHERE id = ?";
+
+pub const CREATE_DOWNLOADS_TABLE: &str = "
+    CREATE TABLE IF NOT EXISTS Downloads (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        song_id INTEGER NOT NULL UNIQUE,
+        state INTEGER NOT NULL,
+        priority INTEGER NOT NULL,
+        error INTEGER NOT NULL,
+        FOREIGN KEY (song_id) REFERENCES Songs(id) ON DELETE CASCADE
+    );
+";
+
+// Replacing the row gives it a new id, sending it to the back of its priority
+pub const INSERT_DOWNLOAD: &str = "
+    INSERT OR REPLACE INTO Downloads
+    VALUES(null, ?, ?, ?, ?)
+";
+
+pub const UPDATE_DOWNLOAD_STATE: &str = "UPDATE Downloads SET state = ?, error = ? WHERE song_id = ?";
+pub const REMOVE_DOWNLOAD: &str = "DELETE FROM Downloads WHERE song_id = ?";
+pub const REMOVE_DOWNLOADS_BY_STATE: &str = "DELETE FROM Downloads WHERE state = ?";
+pub const SELECT_DOWNLOADS: &str = "
+    SELECT Downloads.state, Downloads.priority, Downloads.error, Songs.*
+    FROM Downloads JOIN Songs ON Songs.id = Downloads.song_id
+    ORDER BY Downloads.priority DESC, Downloads.id
+";
@@ -0,0 +1,121 @@
+use std::fs::remove_file;
+use std::fs::rename;
+use std::path::Path;
+use std::path::PathBuf;
+use std::process::Stdio;
+
+use tokio::process::Command;
+
+use crate::backend::database_interface::DatabaseInterface;
+use crate::backend::database_manager::DataLink;
+use crate::backend::error::ResonateError;
+use crate::backend::music::Song;
+use crate::backend::music::NO_ALBUM;
+
+/// Containers ffmpeg can attach a cover picture to
+const COVER_EXTENSIONS: [&str; 3] = ["m4a", "mp3", "flac"];
+
+fn album(song: &Song) -> Option<&str> {
+    song.album.as_deref().filter(|album| *album != NO_ALBUM)
+}
+
+/// The fullsize cover the thumbnail manager saved for this song's album, if there is one.
+/// Songs without an album share a thumbnail, so they get no cover rather than someone else's.
+fn cover_path(thumbnail_dir: &Path, song: &Song) -> Option<PathBuf> {
+    album(song)?;
+    Some(thumbnail_dir.join(song.get_thumbnail_identifier()).join("fullsize.png")).filter(|path| path.exists())
+}
+
+/// Write the song's title, artist and album, and its album cover where the format allows, into its file.
+/// ffmpeg remuxes into a temporary file next to it without re-encoding, which then replaces the original.
+pub async fn tag_song(ffmpeg_path: PathBuf, thumbnail_dir: PathBuf, song: Song) -> Result<(), ResonateError> {
+    let music_path = match song.music_path.as_ref() {
+        Some(music_path) => music_path,
+        None => return Err(ResonateError::DirectoryNotFound)
+    };
+
+    let extension = music_path.extension().map(|extension| extension.to_string_lossy().to_lowercase()).unwrap_or_default();
+    let cover = match COVER_EXTENSIONS.contains(&extension.as_str()) {
+        true => cover_path(&thumbnail_dir, &song),
+        false => None
+    };
+
+    // Counts as a partial download, so one left behind by a crash is cleaned up on the next start
+    let temporary = music_path.with_file_name(format!("{}.temp.{extension}", song.yt_id));
+
+    let mut cmd = Command::new(ffmpeg_path);
+    cmd.arg("-y")
+        .arg("-loglevel")
+        .arg("error")
+        .arg("-i")
+        .arg(music_path);
+
+    if let Some(cover) = cover.as_ref() {
+        cmd.arg("-i").arg(cover);
+    }
+
+    cmd.arg("-map").arg("0:a");
+    if cover.is_some() {
+        cmd.arg("-map").arg("1:v").arg("-disposition:v").arg("attached_pic");
+    }
+
+    cmd.arg("-c")
+        .arg("copy")
+        .arg("-metadata")
+        .arg(format!("title={}", song.title))
+        .arg("-metadata")
+        .arg(format!("artist={}", song.artist))
+        .arg("-metadata")
+        .arg(format!("album={}", album(&song).unwrap_or("")));
+
+    if extension == "mp3" {
+        // The version most players read
+        cmd.arg("-id3v2_version").arg("3");
+    }
+
+    cmd.arg(&temporary)
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped())
+        .kill_on_drop(true);
+
+    #[cfg(windows)]
+    {
+        cmd.creation_flags(0x08000000);
+    }
+
+    let output = match cmd.output().await {
+        Ok(output) => output,
+        Err(_) => return Err(ResonateError::ExecNotFound)
+    };
+
+    if !output.status.success() || rename(&temporary, music_path).is_err() {
+        println!("[TAGGING] Failed to tag {}: {}", song.yt_id, String::from_utf8_lossy(&output.stderr).trim());
+        let _ = remove_file(&temporary);
+        return Err(ResonateError::STDOUTError);
+    }
+
+    Ok(())
+}
+
+/// Write the current database metadata into every downloaded song, one at a time.
+/// Returns how many were tagged out of how many were tried.
+pub async fn retag_library(
+    database: DataLink, ffmpeg_path: PathBuf, music_path: PathBuf, thumbnail_dir: PathBuf
+) -> (usize, usize) {
+    let songs = tokio::task::spawn_blocking(move || DatabaseInterface::blocking_select_all_songs(database, music_path))
+        .await
+        .unwrap_or_default();
+
+    let downloaded: Vec<Song> = songs.into_iter().filter(|song| song.music_path.is_some()).collect();
+    let total = downloaded.len();
+    let mut tagged = 0;
+
+    for song in downloaded {
+        if tag_song(ffmpeg_path.clone(), thumbnail_dir.clone(), song).await.is_ok() {
+            tagged += 1;
+        }
+    }
+
+    println!("[TAGGING] Tagged {tagged} of {total} songs");
+    (tagged, total)
+}
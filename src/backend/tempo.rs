@@ -0,0 +1,295 @@
+use std::collections::VecDeque;
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::sync::atomic::AtomicU64;
+use std::sync::atomic::Ordering;
+use std::time::Duration;
+
+use rodio::Sample;
+use rodio::Source;
+use rodio::source::SeekError;
+
+pub const MIN_SPEED: f32 = 0.5;
+pub const MAX_SPEED: f32 = 2.0;
+
+/// Length of the crossfade between stretched segments, and therefore the output hop
+const OVERLAP_SECONDS: f64 = 0.02;
+
+/// How far either side of the nominal position WSOLA searches for the best matching segment
+const SEEK_SECONDS: f64 = 0.01;
+
+/// Frames produced per batch when resampling
+const RESAMPLE_BATCH: usize = 512;
+
+/// Only every nth sample of the mono downmix is compared when searching for a segment
+const CORRELATION_STRIDE: usize = 4;
+
+struct TempoState {
+    speed: f32,
+    preserve_pitch: bool,
+    version: usize
+}
+
+/// Shared between the audio thread and every `Tempo` source so speed can change mid-song
+#[derive(Clone)]
+pub struct TempoControl {
+    state: Arc<Mutex<TempoState>>
+}
+
+impl TempoControl {
+    pub fn new(speed: f32, preserve_pitch: bool) -> Self {
+        Self { state: Arc::new(Mutex::new(TempoState {
+            speed: speed.clamp(MIN_SPEED, MAX_SPEED), preserve_pitch, version: 0
+        })) }
+    }
+
+    pub fn set_speed(&self, speed: f32) {
+        if let Ok(mut state) = self.state.lock() {
+            state.speed = speed.clamp(MIN_SPEED, MAX_SPEED);
+            state.version += 1;
+        }
+    }
+
+    pub fn set_preserve_pitch(&self, preserve_pitch: bool) {
+        if let Ok(mut state) = self.state.lock() {
+            state.preserve_pitch = preserve_pitch;
+            state.version += 1;
+        }
+    }
+
+    fn changed_since(&self, version: usize) -> Option<(f32, bool, usize)> {
+        match self.state.lock() {
+            Ok(state) if state.version != version => Some((state.speed, state.preserve_pitch, state.version)),
+            _ => None
+        }
+    }
+}
+
+/// Position within the song itself rather than the wall clock time the sink has been playing for
+#[derive(Clone, Default)]
+pub struct Playhead {
+    seconds: Arc<AtomicU64>
+}
+
+impl Playhead {
+    pub fn seconds(&self) -> f32 {
+        f64::from_bits(self.seconds.load(Ordering::Relaxed)) as f32
+    }
+
+    fn set(&self, seconds: f64) {
+        self.seconds.store(seconds.to_bits(), Ordering::Relaxed);
+    }
+}
+
+/// Changes playback speed, either by resampling (pitch follows speed) or by
+/// WSOLA time stretching (pitch is kept)
+pub struct Tempo<S> {
+    source: S,
+    control: TempoControl,
+    playhead: Playhead,
+    version: Option<usize>,
+    speed: f64,
+    preserve_pitch: bool,
+
+    channels: usize,
+    sample_rate: u32,
+    overlap: usize,
+    seek: usize,
+
+    /// Interleaved input, `base` is the absolute frame index of its first frame
+    input: Vec<f32>,
+    base: usize,
+    exhausted: bool,
+
+    /// Absolute input frame the next output is read from
+    position: f64,
+    /// Absolute input frame where the previous stretched segment would naturally continue
+    tail: Option<usize>,
+
+    output: VecDeque<f32>
+}
+
+impl<S> Tempo<S> where S: Source, S::Item: Sample {
+    pub fn new(source: S, control: TempoControl, playhead: Playhead) -> Self {
+        let channels = source.channels().max(1) as usize;
+        let sample_rate = source.sample_rate().max(1);
+        let mut tempo = Self {
+            source,
+            control,
+            playhead,
+            version: None,
+            speed: 1.0,
+            preserve_pitch: false,
+            channels,
+            sample_rate,
+            overlap: ((sample_rate as f64 * OVERLAP_SECONDS) as usize).max(1),
+            seek: (sample_rate as f64 * SEEK_SECONDS) as usize,
+            input: Vec::new(),
+            base: 0,
+            exhausted: false,
+            position: 0.0,
+            tail: None,
+            output: VecDeque::new()
+        };
+        tempo.playhead.set(0.0);
+        tempo.refresh();
+        tempo
+    }
+
+    fn refresh(&mut self) {
+        let (speed, preserve_pitch, version) = match self.control.changed_since(self.version.unwrap_or(usize::MAX)) {
+            Some(update) => update,
+            None => return
+        };
+
+        self.version = Some(version);
+        self.speed = speed as f64;
+        self.preserve_pitch = preserve_pitch;
+    }
+
+    fn stretching(&self) -> bool {
+        self.preserve_pitch && self.speed != 1.0
+    }
+
+    fn frames(&self) -> usize {
+        self.input.len() / self.channels
+    }
+
+    /// Make sure every frame before `end` (absolute) has been read, if the source has that many
+    fn fill(&mut self, end: usize) {
+        while !self.exhausted && self.base + self.frames() < end {
+            match self.source.next() {
+                Some(sample) => self.input.push(sample.to_f32()),
+                None => self.exhausted = true
+            }
+        }
+    }
+
+    /// Forget input that can no longer be read
+    fn discard(&mut self, before: usize) {
+        if before <= self.base { return; }
+        let frames = (before - self.base).min(self.frames());
+        self.input.drain(..frames * self.channels);
+        self.base += frames;
+    }
+
+    fn sample(&self, frame: usize, channel: usize) -> f32 {
+        match frame.checked_sub(self.base) {
+            Some(index) => self.input.get(index * self.channels + channel).copied().unwrap_or(0.0),
+            None => 0.0
+        }
+    }
+
+    fn mono(&self, frame: usize) -> f32 {
+        (0..self.channels).map(|channel| self.sample(frame, channel)).sum::<f32>()
+    }
+
+    /// Resample with linear interpolation, pitch follows speed
+    fn resample(&mut self) -> bool {
+        // If stretching resumes later it starts cleanly from wherever we are then
+        self.tail = None;
+
+        for _ in 0..RESAMPLE_BATCH {
+            let frame = self.position.floor() as usize;
+            self.fill(frame + 2);
+            if frame >= self.base + self.frames() {
+                self.discard(frame);
+                return !self.output.is_empty();
+            }
+
+            let fraction = (self.position - frame as f64) as f32;
+            for channel in 0..self.channels {
+                let (a, b) = (self.sample(frame, channel), self.sample(frame + 1, channel));
+                self.output.push_back(a + (b - a) * fraction);
+            }
+
+            self.position += self.speed;
+        }
+        self.discard(self.position.floor() as usize);
+        true
+    }
+
+    /// One WSOLA step: find the segment near the nominal position that best matches how
+    /// the previous segment would have continued, then crossfade into it
+    fn stretch(&mut self) -> bool {
+        let nominal = self.position.floor() as usize;
+        let tail = self.tail.unwrap_or(nominal);
+        let lowest = nominal.saturating_sub(self.seek).max(self.base);
+
+        self.fill((nominal + self.seek).max(tail) + self.overlap);
+        let available = self.base + self.frames();
+        if nominal >= available { return false; }
+
+        let mut best = nominal;
+        if self.tail.is_some() {
+            let mut best_score = f32::MIN;
+            for candidate in (lowest..=nominal + self.seek).step_by(2) {
+                if candidate + self.overlap > available { break; }
+                let score = (0..self.overlap).step_by(CORRELATION_STRIDE)
+                    .map(|i| self.mono(tail + i) * self.mono(candidate + i))
+                    .sum::<f32>();
+                if score > best_score {
+                    best_score = score;
+                    best = candidate;
+                }
+            }
+        }
+
+        for i in 0..self.overlap {
+            let fade = i as f32 / self.overlap as f32;
+            for channel in 0..self.channels {
+                self.output.push_back(
+                    self.sample(tail + i, channel) * (1.0 - fade) + self.sample(best + i, channel) * fade
+                );
+            }
+        }
+
+        self.tail = Some(best + self.overlap);
+        self.position += self.overlap as f64 * self.speed;
+        self.discard(lowest.min(best + self.overlap).min(self.position.floor() as usize));
+        true
+    }
+
+    fn generate(&mut self) -> bool {
+        self.refresh();
+        let produced = match self.stretching() {
+            true => self.stretch(),
+            false => self.resample()
+        };
+        self.playhead.set(self.position / self.sample_rate as f64);
+        produced
+    }
+}
+
+impl<S> Iterator for Tempo<S> where S: Source, S::Item: Sample {
+    type Item = f32;
+
+    fn next(&mut self) -> Option<f32> {
+        if self.output.is_empty() && !self.generate() {
+            return None;
+        }
+        self.output.pop_front()
+    }
+}
+
+impl<S> Source for Tempo<S> where S: Source, S::Item: Sample {
+    fn current_frame_len(&self) -> Option<usize> { None }
+    fn channels(&self) -> u16 { self.channels as u16 }
+    fn sample_rate(&self) -> u32 { self.sample_rate }
+
+    /// Depends on the speed, which can change at any time
+    fn total_duration(&self) -> Option<Duration> { None }
+
+    fn try_seek(&mut self, pos: Duration) -> Result<(), SeekError> {
+        self.source.try_seek(pos)?;
+        let frame = (pos.as_secs_f64() * self.sample_rate as f64) as usize;
+        self.input.clear();
+        self.output.clear();
+        self.base = frame;
+        self.position = frame as f64;
+        self.tail = None;
+        self.exhausted = false;
+        self.playhead.set(pos.as_secs_f64());
+        Ok(())
+    }
+}
@@ -16,7 +16,7 @@ use crate::backend::music::Song;
 
 pub type TnTx = Sender<ThumbnailMessage>;
 pub type TnRx = Receiver<ThumbnailMessage>;
-pub type TnPaths = (PathBuf, PathBuf);
+pub type TnPaths = (Option<PathBuf>, PathBuf);       // yt-dlp, if found yet, and the thumbnail folder
 
 #[derive(Clone, Debug)]
 pub enum ThumbnailError {
@@ -37,6 +37,7 @@ pub enum ThumbnailMessage {
     RequestDownload(Song, Sender<Result<Thumbnail, ThumbnailError>>),
     RequestPath(Song, Sender<Option<Thumbnail>>),
     InternalReturnDownload(Song, Result<Thumbnail, ThumbnailError>, Sender<Result<Thumbnail, ThumbnailError>>),
+    SetDLPPath(PathBuf)
 }
 
 #[derive(Clone, Debug)]
@@ -59,10 +60,11 @@ pub struct ThumbnailManager {
 }
 
 impl ThumbnailManager {
-    pub fn new(dlp_path: &Path, thumbnail_dir: &Path) -> Self {
+    /// Without yt-dlp, downloads fail until `set_dlp_path` is called
+    pub fn new(dlp_path: Option<&Path>, thumbnail_dir: &Path) -> Self {
         let (tx, rx) = unbounded();
         let passoff_tx = tx.clone();
-        let paths = (dlp_path.to_path_buf(), thumbnail_dir.to_path_buf());
+        let paths = (dlp_path.map(Path::to_path_buf), thumbnail_dir.to_path_buf());
         Self {
             _handle: std::thread::spawn(
                  move || Self::run_thread(passoff_tx, rx, paths)
@@ -72,6 +74,10 @@ impl ThumbnailManager {
         }
     }
 
+    pub fn set_dlp_path(&self, dlp_path: PathBuf) {
+        let _ = self.task_sender.send_blocking(ThumbnailMessage::SetDLPPath(dlp_path));
+    }
+
     pub fn get_default(&self) -> Thumbnail {
         Thumbnail { 
             thumbnail: self.default_thumbnail.to_path_buf(),
@@ -98,18 +104,19 @@ impl ThumbnailManager {
         }
     }
 
-    pub fn download_thread(task_sender: TnTx, task_receiver: TnRx, paths: TnPaths) {
+    pub fn download_thread(task_sender: TnTx, task_receiver: TnRx, mut paths: TnPaths) {
         while let Ok(task) = task_receiver.recv_blocking() {
             match task {
                 ThumbnailMessage::RequestDownload(song, callback) => {
                     let _ = task_sender.send_blocking(
                         ThumbnailMessage::InternalReturnDownload(
                             song.clone(),
-                            Self::download_thumbnails(&song, paths.0.as_path(), paths.1.as_path()),
+                            Self::download_thumbnails(&song, paths.0.as_deref(), paths.1.as_path()),
                             callback
                         )
                     );
                 },
+                ThumbnailMessage::SetDLPPath(dlp_path) => paths.0 = Some(dlp_path),
                 _ => println!("[THUMBNAIL DOWNLOADER] Received unsanctioned task")
             }
         }
@@ -183,6 +190,11 @@ impl ThumbnailManager {
                     let _ = callback.send_blocking(result);
                 },
 
+                // Passed on, only the download thread runs yt-dlp
+                ThumbnailMessage::SetDLPPath(dlp_path) => {
+                    let _ = download_sender.send_blocking(ThumbnailMessage::SetDLPPath(dlp_path));
+                },
+
                 ThumbnailMessage::RequestPath(song, callback) => {
                     // Grab the identifier and check if it exists
                     let identifier = song.get_thumbnail_identifier();
@@ -193,7 +205,12 @@ impl ThumbnailManager {
     }
 
 
-    fn download_thumbnails(song: &Song, dlp_path: &Path, thumbnail_dir: &Path) -> Result<Thumbnail, ThumbnailError> {
+    fn download_thumbnails(song: &Song, dlp_path: Option<&Path>, thumbnail_dir: &Path) -> Result<Thumbnail, ThumbnailError> {
+        let dlp_path = match dlp_path {
+            Some(dlp_path) => dlp_path,
+            None => return Err(ThumbnailError::FailedToSpawnDLP)
+        };
+
         // Get the thing this thumbnail will be saved as
         let identifier = song.get_thumbnail_identifier();
         let webp_path = thumbnail_dir.join(identifier.as_str());
@@ -0,0 +1,74 @@
+use std::env;
+use std::path::Path;
+use std::path::PathBuf;
+
+/// Programs Resonate runs but doesn't ship with
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Tool {
+    Dlp,
+    Ffmpeg
+}
+
+impl Tool {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Tool::Dlp => "yt-dlp",
+            Tool::Ffmpeg => "ffmpeg"
+        }
+    }
+
+    /// Overrides both the configured path and any discovered copy
+    fn env_var(&self) -> &'static str {
+        match self {
+            Tool::Dlp => "RESONATE_YT_DLP",
+            Tool::Ffmpeg => "RESONATE_FFMPEG"
+        }
+    }
+
+    /// File name as it would appear on PATH
+    pub fn executable(&self) -> String {
+        format!("{}{}", self.as_str(), env::consts::EXE_SUFFIX)
+    }
+}
+
+/// Where the copy of a tool in use came from
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ToolSource {
+    Environment,
+    Settings,
+    Bundled,        // In the dependencies folder, installed and kept up to date by Resonate
+    SystemPath
+}
+
+impl ToolSource {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ToolSource::Environment => "ENV",
+            ToolSource::Settings => "CONFIG",
+            ToolSource::Bundled => "BUNDLED",
+            ToolSource::SystemPath => "PATH"
+        }
+    }
+}
+
+pub fn find_on_path(tool: Tool) -> Option<PathBuf> {
+    let paths = env::var_os("PATH")?;
+    env::split_paths(&paths).map(|dir| dir.join(tool.executable())).find(|path| path.is_file())
+}
+
+/// The environment variable wins, then the configured path, then the bundled copy, then PATH.
+/// Overrides that don't point at a file are ignored rather than leaving the tool missing.
+pub fn locate(tool: Tool, configured: Option<&Path>, bundled: Option<PathBuf>) -> Option<(PathBuf, ToolSource)> {
+    let from_env = env::var_os(tool.env_var()).map(PathBuf::from).filter(|path| path.is_file());
+    let configured = configured.map(Path::to_path_buf).filter(|path| path.is_file());
+
+    if let Some(path) = from_env {
+        Some((path, ToolSource::Environment))
+    } else if let Some(path) = configured {
+        Some((path, ToolSource::Settings))
+    } else if let Some(path) = bundled.filter(|path| path.is_file()) {
+        Some((path, ToolSource::Bundled))
+    } else {
+        find_on_path(tool).map(|path| (path, ToolSource::SystemPath))
+    }
+}
@@ -0,0 +1,221 @@
+use std::collections::VecDeque;
+use std::f32::consts::PI;
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::time::Duration;
+
+use rodio::Sample;
+use rodio::Source;
+use rodio::source::SeekError;
+
+pub const SPECTRUM_BANDS: usize = 32;
+
+/// How many peak readings the rolling history keeps, one per update
+pub const PEAK_HISTORY: usize = 64;
+
+/// Samples per FFT, must be a power of two
+const FFT_SIZE: usize = 2048;
+
+const MIN_FREQUENCY: f32 = 40.0;
+const MAX_FREQUENCY: f32 = 16000.0;
+
+/// Anything quieter than this shows as an empty band
+const FLOOR_DB: f32 = -60.0;
+
+/// Mono samples collected before the shared buffer is locked
+const TAP_BATCH: usize = 256;
+
+/// One frame of visualiser data, every value is 0 to 1
+#[derive(Debug, Clone, PartialEq)]
+pub struct VisualUpdate {
+    pub bands: [f32; SPECTRUM_BANDS],
+    pub peaks: Vec<f32>            // Oldest first
+}
+
+struct TapState {
+    samples: VecDeque<f32>,
+    sample_rate: u32,
+    peak: f32
+}
+
+/// The most recent mono samples heading to the output, shared with the audio thread
+#[derive(Clone)]
+pub struct Tap {
+    state: Arc<Mutex<TapState>>
+}
+
+impl Tap {
+    pub fn new() -> Self {
+        Self { state: Arc::new(Mutex::new(TapState {
+            samples: VecDeque::with_capacity(FFT_SIZE), sample_rate: 44100, peak: 0.0
+        })) }
+    }
+
+    fn push(&self, batch: &[f32], sample_rate: u32) {
+        if let Ok(mut state) = self.state.lock() {
+            state.sample_rate = sample_rate;
+            for sample in batch {
+                state.peak = state.peak.max(sample.abs());
+                state.samples.push_back(*sample);
+            }
+            let excess = state.samples.len().saturating_sub(FFT_SIZE);
+            state.samples.drain(..excess);
+        }
+    }
+
+    fn clear(&self) {
+        if let Ok(mut state) = self.state.lock() {
+            state.samples.clear();
+            state.peak = 0.0;
+        }
+    }
+
+    /// Copy out the latest samples and the loudest sample since the last read
+    fn take(&self) -> (Vec<f32>, u32, f32) {
+        match self.state.lock() {
+            Ok(mut state) => {
+                let peak = std::mem::take(&mut state.peak);
+                (state.samples.iter().copied().collect(), state.sample_rate, peak)
+            }
+            Err(_) => (Vec::new(), 44100, 0.0)
+        }
+    }
+}
+
+/// Passes samples straight through, copying a mono downmix into the tap
+pub struct Analyser<S> {
+    source: S,
+    tap: Tap,
+    batch: Vec<f32>,
+    frame: f32,
+    channel: u16
+}
+
+impl<S> Analyser<S> where S: Source, S::Item: Sample {
+    pub fn new(source: S, tap: Tap) -> Self {
+        tap.clear();
+        Self { source, tap, batch: Vec::with_capacity(TAP_BATCH), frame: 0.0, channel: 0 }
+    }
+}
+
+impl<S> Iterator for Analyser<S> where S: Source, S::Item: Sample {
+    type Item = f32;
+
+    fn next(&mut self) -> Option<f32> {
+        let sample = self.source.next()?.to_f32();
+        let channels = self.source.channels().max(1);
+
+        self.frame += sample;
+        self.channel += 1;
+        if self.channel >= channels {
+            self.batch.push(self.frame / channels as f32);
+            self.frame = 0.0;
+            self.channel = 0;
+
+            if self.batch.len() >= TAP_BATCH {
+                self.tap.push(&self.batch, self.source.sample_rate());
+                self.batch.clear();
+            }
+        }
+
+        Some(sample)
+    }
+}
+
+impl<S> Source for Analyser<S> where S: Source, S::Item: Sample {
+    fn current_frame_len(&self) -> Option<usize> { self.source.current_frame_len() }
+    fn channels(&self) -> u16 { self.source.channels() }
+    fn sample_rate(&self) -> u32 { self.source.sample_rate() }
+    fn total_duration(&self) -> Option<Duration> { self.source.total_duration() }
+
+    fn try_seek(&mut self, pos: Duration) -> Result<(), SeekError> {
+        self.batch.clear();
+        self.frame = 0.0;
+        self.channel = 0;
+        self.tap.clear();
+        self.source.try_seek(pos)
+    }
+}
+
+/// Turns what the tap has seen into spectrum bands and keeps the peak history
+pub struct Spectrum {
+    window: Vec<f32>,
+    peaks: VecDeque<f32>
+}
+
+impl Spectrum {
+    pub fn new() -> Self {
+        Self {
+            window: (0..FFT_SIZE).map(|i| 0.5 - 0.5 * (2.0 * PI * i as f32 / FFT_SIZE as f32).cos()).collect(),
+            peaks: VecDeque::with_capacity(PEAK_HISTORY)
+        }
+    }
+
+    pub fn update(&mut self, tap: &Tap) -> VisualUpdate {
+        let (samples, sample_rate, peak) = tap.take();
+
+        self.peaks.push_back(peak.min(1.0));
+        if self.peaks.len() > PEAK_HISTORY {
+            self.peaks.pop_front();
+        }
+
+        // Right aligned so the newest audio is always analysed
+        let offset = FFT_SIZE - samples.len().min(FFT_SIZE);
+        let mut buffer = vec![(0f32, 0f32); FFT_SIZE];
+        for (idx, sample) in (offset..FFT_SIZE).zip(samples.iter().skip(samples.len().saturating_sub(FFT_SIZE))) {
+            buffer[idx].0 = sample * self.window[idx];
+        }
+        fft(&mut buffer);
+
+        let bin_width = sample_rate as f32 / FFT_SIZE as f32;
+        let ratio = (MAX_FREQUENCY / MIN_FREQUENCY).powf(1.0 / SPECTRUM_BANDS as f32);
+        let mut bands = [0f32; SPECTRUM_BANDS];
+
+        for (band, value) in bands.iter_mut().enumerate() {
+            let low = MIN_FREQUENCY * ratio.powi(band as i32);
+            let high = low * ratio;
+            let first = ((low / bin_width) as usize).max(1);
+            let last = ((high / bin_width) as usize).max(first).min(FFT_SIZE / 2 - 1);
+
+            let magnitude = buffer[first..=last].iter()
+                .map(|(re, im)| (re * re + im * im).sqrt())
+                .fold(0f32, f32::max) * 4.0 / FFT_SIZE as f32;
+
+            let db = 20.0 * magnitude.max(1e-9).log10();
+            *value = ((db - FLOOR_DB) / -FLOOR_DB).clamp(0.0, 1.0);
+        }
+
+        VisualUpdate { bands, peaks: self.peaks.iter().copied().collect() }
+    }
+}
+
+/// In place iterative radix 2 FFT over (real, imaginary) pairs
+fn fft(buffer: &mut [(f32, f32)]) {
+    let n = buffer.len();
+
+    let mut j = 0;
+    for i in 1..n {
+        let mut bit = n >> 1;
+        while j & bit != 0 {
+            j ^= bit;
+            bit >>= 1;
+        }
+        j |= bit;
+        if i < j { buffer.swap(i, j); }
+    }
+
+    let mut length = 2;
+    while length <= n {
+        let angle = -2.0 * PI / length as f32;
+        for start in (0..n).step_by(length) {
+            for k in 0..length / 2 {
+                let (sin, cos) = (angle * k as f32).sin_cos();
+                let (a, b) = (buffer[start + k], buffer[start + k + length / 2]);
+                let twiddled = (b.0 * cos - b.1 * sin, b.0 * sin + b.1 * cos);
+                buffer[start + k] = (a.0 + twiddled.0, a.1 + twiddled.1);
+                buffer[start + k + length / 2] = (a.0 - twiddled.0, a.1 - twiddled.1);
+            }
+        }
+        length <<= 1;
+    }
+}
@@ -0,0 +1,68 @@
+use std::fs::File;
+use std::fs::read;
+use std::fs::write;
+use std::io::BufReader;
+use std::path::PathBuf;
+
+use rodio::Decoder;
+use rodio::Source;
+
+use crate::backend::music::Song;
+
+/// Number of bars in a waveform overview
+pub const WAVEFORM_POINTS: usize = 120;
+
+/// Samples reduced to a single peak before the final downsample
+const BLOCK: usize = 4096;
+
+/// Peak level across a whole song, normalised so the loudest bar is 1
+pub type Waveform = Vec<f32>;
+
+/// Load the overview from the cache, decoding the song and caching the result if it isn't there.
+/// Blocks while decoding.
+pub fn blocking_waveform(song: &Song, cache_dir: PathBuf) -> Option<Waveform> {
+    let cache = cache_dir.join(format!("{}.waveform", song.yt_id));
+
+    // One byte per bar
+    if let Ok(bytes) = read(&cache) {
+        if bytes.len() == WAVEFORM_POINTS {
+            return Some(bytes.into_iter().map(|byte| byte as f32 / 255f32).collect());
+        }
+    }
+
+    let file = File::open(song.music_path.as_ref()?).ok()?;
+    let decoder = Decoder::new(BufReader::new(file)).ok()?.convert_samples::<f32>();
+
+    let mut blocks: Vec<f32> = Vec::new();
+    let mut peak = 0f32;
+    for (idx, sample) in decoder.enumerate() {
+        peak = peak.max(sample.abs());
+        if idx % BLOCK == BLOCK - 1 {
+            blocks.push(peak);
+            peak = 0f32;
+        }
+    }
+    blocks.push(peak);
+
+    let mut waveform: Waveform = (0..WAVEFORM_POINTS).map(|point| {
+        let start = point * blocks.len() / WAVEFORM_POINTS;
+        let end = ((point + 1) * blocks.len() / WAVEFORM_POINTS).max(start + 1).min(blocks.len());
+        blocks.get(start..end).map(|range| range.iter().copied().fold(0f32, f32::max)).unwrap_or(0f32)
+    }).collect();
+
+    let loudest = waveform.iter().copied().fold(0f32, f32::max);
+    if loudest > 0f32 {
+        waveform.iter_mut().for_each(|point| *point /= loudest);
+    }
+
+    let bytes: Vec<u8> = waveform.iter().map(|point| (point.clamp(0f32, 1f32) * 255f32) as u8).collect();
+    if write(&cache, bytes).is_err() {
+        println!("[WAVEFORM] Failed to cache waveform for {}", song.yt_id);
+    }
+
+    Some(waveform)
+}
+
+pub async fn load_waveform(song: Song, cache_dir: PathBuf) -> Option<Waveform> {
+    tokio::task::spawn_blocking(move || blocking_waveform(&song, cache_dir)).await.ok().flatten()
+}
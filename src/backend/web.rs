@@ -1,209 +1,692 @@
 use std::path::PathBuf;
 
 use std::path::Path;
+use std::fs::read_dir;
+use std::fs::remove_file;
 use std::time::Duration;
-use std::task::Waker;
 use std::pin::Pin;
+use std::collections::HashSet;
 
 use iced::futures::Stream;
-use youtube_dl::YoutubeDl;
+use iced::futures::StreamExt;
+use iced::futures::stream;
+use serde_json::Value;
+use std::process::Stdio;
+
+use async_channel::bounded;
+use async_channel::Receiver;
+use async_channel::Sender;
+use tokio::io::AsyncBufReadExt;
+use tokio::io::AsyncReadExt;
+use tokio::io::BufReader;
 use tokio::process::Command;
 
 use crate::backend::error::ResonateError;
+use crate::backend::error::DownloadError;
 use crate::backend::music::Song;
+use crate::backend::music::AudioFormat;
 use crate::backend::database_manager::DataLink;
 
 use super::database_interface::DatabaseInterface;
 
+/// Fields asked of yt-dlp for each entry of a search or playlist, printed as one JSON object per line.
+/// Flat entries rarely have `artist`, but usually carry the channel or uploader instead.
+const ENTRY_TEMPLATE: &str = "%(.{id,title,artist,channel,uploader,album,duration,playlist_title})j";
+
+/// An entry from a search or playlist. Complete if yt-dlp already gave everything a song needs,
+/// otherwise only the ID is known and the metadata has to be looked up on its own.
+#[derive(Debug, Clone)]
+pub enum SearchHit {
+    Complete(Song),
+    Incomplete(String)
+}
+
+impl SearchHit {
+    pub fn id(&self) -> &str {
+        match self {
+            SearchHit::Complete(song) => &song.yt_id,
+            SearchHit::Incomplete(id) => id
+        }
+    }
+}
+
+/// List the entries behind a search or playlist URL with a single yt-dlp call,
+/// along with the title of the playlist they came from
+async fn flat_entries(
+    executable_path: &Path, url: String
+) -> Result<(Option<String>, Vec<SearchHit>), ResonateError> {
+    let mut cmd = Command::new(executable_path);
+    cmd.arg("--skip-download")
+        .arg("--flat-playlist")
+        .arg("--no-check-certificate")
+        .arg("--print")
+        .arg(ENTRY_TEMPLATE)
+        .arg(url)
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .kill_on_drop(true);
+
+    #[cfg(windows)]
+    {
+        cmd.creation_flags(0x08000000);
+    }
+
+    let output = match cmd.output().await {
+        Ok(output) => output,
+        Err(_) => return Err(ResonateError::ExecNotFound)
+    };
+
+    if !output.status.success() && output.stdout.is_empty() {
+        return Err(ResonateError::NetworkError);
+    }
+
+    let mut playlist_title = None;
+    let hits = String::from_utf8_lossy(&output.stdout).lines().filter_map(|line| {
+        let value = serde_json::from_str::<Value>(line).ok()?;
+        let id = value.get("id").and_then(Value::as_str)?.to_string();
+
+        if playlist_title.is_none() {
+            playlist_title = value.get("playlist_title").and_then(Value::as_str).map(String::from);
+        }
+
+        // Entries without a title are channels and other non-videos
+        value.get("title").and_then(Value::as_str)?;
+
+        Some(match song_from_json(&value) {
+            Some(song) => SearchHit::Complete(song),
+            None => SearchHit::Incomplete(id)
+        })
+    }).collect();
+
+    Ok((playlist_title, hits))
+}
+
 pub async fn flatsearch(
         executable_path: PathBuf,
         query: String
-    ) -> Result<Vec<String>, ResonateError> {
+    ) -> Result<Vec<SearchHit>, ResonateError> {
 
     let url = format!("https://music.youtube.com/search?q={}", query.replace(" ", "+"));
-    match YoutubeDl::new(url)
-        .youtube_dl_path(executable_path)
-        .extra_arg("--skip-download")
-        .extra_arg("--flat-playlist")
-        .extra_arg("--no-check-certificate")
-        .run_async().await {
-        Ok(result) => {
-            match result.into_playlist() {
-                Some(mut playlist) => match playlist.entries.take() {
-                    Some(entries) => Ok(entries.into_iter().filter_map(|entry| {
-                        println!("ENTRY ID: {} ENTRY TITLE: {:?}", entry.id, entry.title);
-                        entry.title.map(|_| entry.id.clone())
-                    }).collect::<Vec<String>>()),
-                    None => Ok(Vec::<String>::new()),
-                },
-                None => Ok(Vec::<String>::new())
-            }
-        }
-        Err(_) => Err(ResonateError::NetworkError)
+    flat_entries(&executable_path, url).await.map(|(_, hits)| hits)
+}
+
+/// Whether a link should be imported through yt-dlp rather than Spotify
+pub fn is_youtube_url(url: &str) -> bool {
+    ["youtube.com/", "youtu.be/"].iter().any(|host| url.contains(host))
+}
+
+/// Name and entries of a YouTube or YouTube Music playlist, album or mix, in playlist order.
+/// Repeated entries are only kept once.
+pub async fn playlist_entries(
+    executable_path: PathBuf, url: String
+) -> Result<(String, Vec<SearchHit>), ResonateError> {
+    let (title, hits) = flat_entries(&executable_path, url).await?;
+    if hits.is_empty() {
+        return Err(ResonateError::NetworkError);
     }
+
+    let mut seen = HashSet::new();
+    let hits = hits.into_iter().filter(|hit| seen.insert(hit.id().to_string())).collect();
+    Ok((title.unwrap_or(String::from("YouTube Playlist")), hits))
+}
+
+/// Build a song from yt-dlp's JSON for a single video. Entries missing an artist or duration aren't songs.
+/// Without an artist tag, the channel that uploaded it stands in.
+fn song_from_json(value: &Value) -> Option<Song> {
+    let field = |name: &str| value.get(name).and_then(Value::as_str).filter(|field| !field.is_empty()).map(String::from);
+    let duration = value.get("duration").and_then(Value::as_f64)?;
+
+    // YouTube Music's auto-generated channels are named "{artist} - Topic"
+    let artist = field("artist")
+        .or_else(|| field("channel"))
+        .or_else(|| field("uploader"))
+        .map(|artist| artist.trim_end_matches(" - Topic").to_string())?;
+
+    Some(Song::new(
+        0,
+        field("id")?,
+        field("title")?,
+        artist,
+        field("album"),
+        Duration::from_secs(duration as u64),
+        None
+    ))
 }
 
-pub fn collect_metadata(
+/// Look up a single video. yt-dlp is killed if the returned future is dropped.
+pub async fn collect_metadata(
         executable_path: &Path,
-        id: &String,
-        music_path: PathBuf
+        id: &str
     ) -> Result<Song, ResonateError> {
 
-    let ytdl = YoutubeDl::new(id)
-        .youtube_dl_path(executable_path)
-        .extra_arg("--no-check-certificate")
-        .extra_arg("--skip-download")
-        .run();
-
-    match ytdl {
-        Ok(result) => {
-            match result.into_single_video() {
-                Some(mut entry) => {
-                    if let (Some(title), Some(artist), Some(duration)) = (entry.title, entry.artist, entry.duration) {
-                        let duration = duration.as_u64().unwrap_or(0u64);
-                        Ok(
-                            Song::new(
-                                0,
-                                entry.id,
-                                title,
-                                artist,
-                                entry.album.take(),
-                                Duration::from_secs(duration),
-                                music_path
-                            )
-                        )
-                    } else {
-                        Err(ResonateError::NetworkError)
-                    }
-                }
-                None => Err(ResonateError::NetworkError)
+    let mut cmd = Command::new(executable_path);
+    cmd.arg("--no-check-certificate")
+        .arg("--skip-download")
+        .arg("--dump-json")
+        .arg(format!("https://music.youtube.com/watch?v={id}"))
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .kill_on_drop(true);
+
+    #[cfg(windows)]
+    {
+        cmd.creation_flags(0x08000000);
+    }
+
+    let output = match cmd.output().await {
+        Ok(output) => output,
+        Err(_) => return Err(ResonateError::ExecNotFound)
+    };
+
+    match serde_json::from_slice::<Value>(&output.stdout) {
+        Ok(value) => song_from_json(&value).ok_or(ResonateError::NetworkError),
+        Err(_) => Err(ResonateError::NetworkError)
+    }
+}
+
+/// Marks the lines yt-dlp prints through `--progress-template` so they can be told apart from its other output
+const PROGRESS_PREFIX: &str = "[resonate]";
+const PROGRESS_TEMPLATE: &str = "download:[resonate] %(progress._percent_str)s|%(progress._speed_str)s|%(progress._eta_str)s|%(progress._total_bytes_str,progress._total_bytes_estimate_str)s";
+
+/// Attempts made before a transient failure is given up on
+const MAX_ATTEMPTS: u32 = 4;
+
+/// Wait before the first retry, doubling after each one
+const RETRY_DELAY: Duration = Duration::from_secs(2);
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct DownloadProgress {
+    pub yt_id: String,
+    pub percent: f32,
+    pub speed: Option<String>,
+    pub eta: Option<String>,
+    pub size: Option<String>,
+    pub post_processing: bool,         // Downloaded, yt-dlp is converting or tagging the file
+    pub retrying: Option<(u32, DownloadError)>     // Attempt that just failed and why, while waiting to retry
+}
+
+impl DownloadProgress {
+    pub fn new(yt_id: String) -> Self {
+        Self { yt_id, ..Default::default() }
+    }
+
+    /// Read a line of yt-dlp output, returning the new progress if it said anything about it
+    fn parse(&self, line: &str) -> Option<DownloadProgress> {
+        let line = line.trim();
+
+        if let Some(fields) = line.strip_prefix(PROGRESS_PREFIX) {
+            let mut fields = fields.split('|').map(str::trim);
+            let percent = fields.next()?.trim_end_matches('%').trim().parse::<f32>().ok()?;
+            let known = |field: Option<&str>| field
+                // yt-dlp prints NA for a field it has no value for at all
+                .filter(|field| !field.is_empty() && *field != "NA" && !field.contains("N/A") && !field.starts_with("Unknown"))
+                .map(String::from);
+
+            return Some(DownloadProgress {
+                yt_id: self.yt_id.clone(),
+                percent: percent.clamp(0f32, 100f32),
+                speed: known(fields.next()),
+                eta: known(fields.next()),
+                size: known(fields.next()),
+                post_processing: false,
+                retrying: None
+            });
+        }
+
+        // Anything else yt-dlp reports after the download is post-processing
+        match ["[ExtractAudio]", "[FixupM4a]", "[Metadata]", "[EmbedThumbnail]"].iter().any(|stage| line.starts_with(stage)) {
+            true => Some(DownloadProgress {
+                yt_id: self.yt_id.clone(),
+                percent: 100f32,
+                speed: None,
+                eta: None,
+                size: self.size.clone(),
+                post_processing: true,
+                retrying: None
+            }),
+            false => None
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum StopRequest {
+    Cancel,     // Give up on the song and remove what was downloaded
+    Pause       // Stop for now, keeping partial files so yt-dlp can resume
+}
+
+/// How a call to `download_song` ended
+#[derive(Debug, Clone)]
+pub enum DownloadOutcome {
+    Finished(Song),
+    Failed(Song, DownloadError),
+    Stopped(Song, StopRequest)      // Asked for, not a failure
+}
+
+/// Why a single run of yt-dlp didn't finish
+enum Interruption {
+    Failed(DownloadError),
+    Stopped(StopRequest)
+}
+
+/// Held by the interface while a download runs so it can be stopped
+#[derive(Debug, Clone)]
+pub struct DownloadHandle {
+    stop: Sender<StopRequest>
+}
+
+impl DownloadHandle {
+    /// The receiver goes to `download_song`
+    pub fn new() -> (Self, Receiver<StopRequest>) {
+        let (stop, receiver) = bounded(1);
+        (Self { stop }, receiver)
+    }
+
+    pub fn cancel(&self) {
+        let _ = self.stop.try_send(StopRequest::Cancel);
+    }
+
+    pub fn pause(&self) {
+        let _ = self.stop.try_send(StopRequest::Pause);
+    }
+}
+
+/// Whether a file in the music folder is something yt-dlp left behind rather than a finished song
+fn is_partial_download(name: &str) -> bool {
+    name.ends_with(".part") || name.ends_with(".ytdl") || name.contains(".temp.") || name.contains(".part-Frag")
+}
+
+/// Remove everything yt-dlp wrote for `yt_id`. Only used once a download has been abandoned,
+/// at which point nothing under that id is a finished song.
+fn remove_download_files(music_path: &Path, yt_id: &str) {
+    let prefix = format!("{yt_id}.");
+    if let Ok(entries) = read_dir(music_path) {
+        for entry in entries.flatten() {
+            if entry.file_name().to_string_lossy().starts_with(&prefix) {
+                let _ = remove_file(entry.path());
             }
         }
-        Err(_) => Err(ResonateError::NetworkError)
     }
 }
 
-pub async fn download_song(dlp_path: Option<PathBuf>, music_path: PathBuf, song: Song) -> Result<Song, Song> {
-    let dlp_path = match dlp_path {
-        Some(dlp_path) => dlp_path,
-        None => return Err(song)
-    };
+/// Clear out temporary files from downloads that never finished, e.g. because the app was killed
+pub fn remove_partial_downloads(music_path: &Path) {
+    if let Ok(entries) = read_dir(music_path) {
+        for entry in entries.flatten() {
+            if is_partial_download(&entry.file_name().to_string_lossy()) {
+                let _ = remove_file(entry.path());
+            }
+        }
+    }
+}
 
-    let output = music_path.join(format!("{}.mp3", song.yt_id));
-    let url = format!("https://music.youtube.com/watch?v={}", song.yt_id);
+/// Work out why yt-dlp failed from its error output.
+/// It exits with 1 on any error, so the exit code alone says very little.
+fn classify_failure(stderr: &str) -> DownloadError {
+    let stderr = stderr.to_lowercase();
+    let mentions = |needles: &[&str]| needles.iter().any(|needle| stderr.contains(needle));
+
+    if mentions(&["no space left on device", "errno 28", "disk quota exceeded"]) {
+        DownloadError::DiskFull
+    } else if mentions(&["not made this video available in your country", "not available in your country", "geo restrict", "geo-restrict"]) {
+        DownloadError::GeoBlocked
+    } else if mentions(&["video unavailable", "private video", "has been removed", "members-only", "join this channel", "sign in to confirm your age", "this video is not available"]) {
+        DownloadError::Unavailable
+    } else if mentions(&["postprocessing", "ffmpeg not found", "ffprobe and ffmpeg not found", "conversion failed"]) {
+        DownloadError::PostProcessing
+    } else if mentions(&["unable to download", "timed out", "connection", "network is unreachable", "name resolution", "getaddrinfo", "http error 5", "http error 429", "incompleteread", "ssl"]) {
+        DownloadError::Network
+    } else {
+        DownloadError::Unknown
+    }
+}
+
+/// Run yt-dlp once, reporting progress as it goes
+async fn attempt_download(
+    dlp_path: &Path, ffmpeg_path: Option<&Path>, music_path: &Path, yt_id: &str, format: AudioFormat,
+    progress: &Sender<DownloadProgress>, stop: &Receiver<StopRequest>
+) -> Result<(), Interruption> {
+    // yt-dlp fills in the extension once it knows what it ended up with
+    let output = music_path.join(format!("{yt_id}.%(ext)s"));
+    let url = format!("https://music.youtube.com/watch?v={yt_id}");
 
     let mut cmd = Command::new(dlp_path);
     cmd.arg("-f")
-        .arg("bestaudio")
+        .arg(format.selector())
         .arg("--extract-audio")
         .arg("--audio-format")
-        .arg("mp3")
+        .arg(format.as_str())
         .arg("-o")
         .arg(output.to_string_lossy().to_string())
         .arg("--no-check-certificate")
-        .arg(url);
+        .arg("--newline")
+        .arg("--progress-template")
+        .arg(PROGRESS_TEMPLATE)
+        .arg(url)
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .kill_on_drop(true);
+
+    // Otherwise yt-dlp looks for ffmpeg on PATH itself
+    if let Some(ffmpeg_path) = ffmpeg_path {
+        cmd.arg("--ffmpeg-location").arg(ffmpeg_path);
+    }
 
     #[cfg(windows)]
     {
         cmd.creation_flags(0x08000000);
     }
-    
-    let mut ytdlp = cmd.spawn().unwrap();
-    let status = ytdlp.wait().await;
+
+    let mut ytdlp = match cmd.spawn() {
+        Ok(ytdlp) => ytdlp,
+        Err(_) => return Err(Interruption::Failed(DownloadError::ExecutableMissing))
+    };
+    let stdout = ytdlp.stdout.take();
+    let stderr = ytdlp.stderr.take();
+
+    let follow = async {
+        let follow_stdout = async {
+            if let Some(stdout) = stdout {
+                let mut lines = BufReader::new(stdout).lines();
+                let mut current = DownloadProgress::new(yt_id.to_string());
+                while let Ok(Some(line)) = lines.next_line().await {
+                    if let Some(update) = current.parse(&line) {
+                        if update != current {
+                            current = update;
+                            let _ = progress.send(current.clone()).await;
+                        }
+                    }
+                }
+            }
+        };
+
+        let read_stderr = async {
+            let mut errors = String::new();
+            if let Some(mut stderr) = stderr {
+                let _ = stderr.read_to_string(&mut errors).await;
+            }
+            errors
+        };
+
+        let (_, errors) = tokio::join!(follow_stdout, read_stderr);
+        (ytdlp.wait().await, errors)
+    };
+
+    // A dropped handle is not a cancellation, only an explicit request is
+    let (status, errors) = tokio::select! {
+        finished = follow => finished,
+        Ok(request) = stop.recv() => {
+            let _ = ytdlp.kill().await;
+            return Err(Interruption::Stopped(request));
+        }
+    };
+
     println!("[YT-DLP] Status: {status:?}");
+    match status {
+        Ok(status) if status.success() => Ok(()),
+        Ok(_) => Err(Interruption::Failed(classify_failure(&errors))),
+        Err(_) => Err(Interruption::Failed(DownloadError::Unknown))
+    }
+}
+
+/// Download a song, retrying transient failures with exponential backoff
+pub async fn download_song(
+    dlp_path: Option<PathBuf>, ffmpeg_path: Option<PathBuf>, music_path: PathBuf, mut song: Song, format: AudioFormat,
+    progress: Sender<DownloadProgress>, stop: Receiver<StopRequest>
+) -> DownloadOutcome {
+    let dlp_path = match dlp_path {
+        Some(dlp_path) => dlp_path,
+        None => return DownloadOutcome::Failed(song, DownloadError::ExecutableMissing)
+    };
+
+    let mut attempt = 1;
+    let mut delay = RETRY_DELAY;
 
-    match output.exists() {
-        true => Ok(song),
-        false => Err(song)
+    loop {
+        let error = match attempt_download(&dlp_path, ffmpeg_path.as_deref(), &music_path, &song.yt_id, format, &progress, &stop).await {
+            Ok(()) => {
+                song.load_music_path(music_path.clone());
+                // yt-dlp was happy but there is no playable file, so the conversion went wrong
+                match song.music_path.is_some() {
+                    true => return DownloadOutcome::Finished(song),
+                    false => DownloadError::PostProcessing
+                }
+            }
+            Err(Interruption::Failed(error)) => error,
+            Err(Interruption::Stopped(request)) => return stopped(song, request, &music_path)
+        };
+
+        println!("[YT-DLP] Attempt {attempt} at {} failed: {error:?}", song.yt_id);
+        if !error.is_transient() || attempt >= MAX_ATTEMPTS {
+            // Nothing under this id is a finished song, so whatever yt-dlp left can go
+            remove_download_files(&music_path, &song.yt_id);
+            return DownloadOutcome::Failed(song, error);
+        }
+
+        let _ = progress.send(DownloadProgress {
+            retrying: Some((attempt, error)),
+            ..DownloadProgress::new(song.yt_id.clone())
+        }).await;
+
+        tokio::select! {
+            _ = tokio::time::sleep(delay) => (),
+            Ok(request) = stop.recv() => return stopped(song, request, &music_path)
+        }
+
+        attempt += 1;
+        delay *= 2;
     }
 }
+
+/// Partial files are kept on a pause so yt-dlp can resume from them
+fn stopped(song: Song, request: StopRequest, music_path: &Path) -> DownloadOutcome {
+    if request == StopRequest::Cancel {
+        remove_download_files(music_path, &song.yt_id);
+    }
+    DownloadOutcome::Stopped(song, request)
+}
+
+/// Metadata lookups allowed to run at once
+const METADATA_WORKERS: usize = 4;
+
+/// Turns search results into saved songs a few at a time.
+/// Dropping the pool, e.g. by aborting its task, kills any yt-dlp processes still running.
 pub struct AsyncMetadataCollectionPool {
-    handle: Option<std::thread::JoinHandle<Result<Song, ResonateError>>>,
-    dlp_path: PathBuf,
-    music_path: PathBuf,
-    database: DataLink,
-    ids: Vec<String>
+    results: Pin<Box<dyn Stream<Item = Result<Song, ()>> + Send>>
 }
 
 impl AsyncMetadataCollectionPool {
+    /// Yields songs in the order they finish, skipping any already in the library
     pub fn new(
         database: DataLink,
-        mut ids: Vec<String>,
+        hits: Vec<SearchHit>,
         dlp_path: PathBuf,
         music_path: PathBuf,
     ) -> Self {
-        ids.reverse();
-        Self {
-            handle: None,
-            dlp_path,
-            music_path,
-            database,
-            ids
-        }
+        let mut seen = HashSet::new();
+        let hits: Vec<SearchHit> = hits.into_iter().filter(|hit| seen.insert(hit.id().to_string())).collect();
+
+        let results = stream::iter(hits)
+            .map(move |hit| populate(hit, database.clone(), dlp_path.clone(), music_path.clone(), false))
+            .buffer_unordered(METADATA_WORKERS)
+            .map(|result| result.map_err(|_| ()));
+
+        Self { results: Box::pin(results) }
+    }
+
+    /// Yields songs in the order given, with songs already in the library handed back as they are
+    pub fn import(
+        database: DataLink,
+        hits: Vec<SearchHit>,
+        dlp_path: PathBuf,
+        music_path: PathBuf,
+    ) -> Self {
+        let results = stream::iter(hits)
+            .map(move |hit| populate(hit, database.clone(), dlp_path.clone(), music_path.clone(), true))
+            .buffered(METADATA_WORKERS)
+            .map(|result| result.map_err(|_| ()));
+
+        Self { results: Box::pin(results) }
     }
 }
 
-fn populate(
-    waker: Waker, id: String, database: DataLink, dlp_path: PathBuf, music_path: PathBuf
+async fn populate(
+    hit: SearchHit, database: DataLink, dlp_path: PathBuf, music_path: PathBuf, keep_existing: bool
 ) -> Result<Song, ResonateError> {
 
-    if !DatabaseInterface::blocking_is_unique(database.clone(), id.clone()) {
-        return Err(ResonateError::AlreadyExists);
+    let unique = {
+        let (database, id) = (database.clone(), hit.id().to_string());
+        tokio::task::spawn_blocking(move || DatabaseInterface::blocking_is_unique(database, id)).await.unwrap_or(false)
+    };
+
+    if !unique {
+        return match keep_existing {
+            true => DatabaseInterface::select_song_by_youtube_id(database, hit.id().to_string(), music_path).await
+                .ok_or(ResonateError::SQLError),
+            false => Err(ResonateError::AlreadyExists)
+        };
     }
 
-    let mut song = match collect_metadata(dlp_path.as_path(), &id, music_path) {
-        Ok(song) => song,
-        error => return error
+    // Only entries the search came back without full metadata for need their own lookup
+    let mut song = match hit {
+        SearchHit::Complete(song) => song,
+        SearchHit::Incomplete(id) => collect_metadata(dlp_path.as_path(), &id).await?
     };
 
-    let id = match DatabaseInterface::blocking_insert_song(database, song.clone()) {
-        Ok(id) => id,
-        Err(_) => return Err(ResonateError::SQLError)
+    let inserted = song.clone();
+    song.id = match tokio::task::spawn_blocking(move || DatabaseInterface::blocking_insert_song(database, inserted)).await {
+        Ok(Ok(id)) => id,
+        _ => return Err(ResonateError::SQLError)
     };
 
-    waker.wake();
-    song.id = id;
     Ok(song)
 }
 
 impl Stream for AsyncMetadataCollectionPool {
     type Item = Result<Song, ()>;
-    
+
     fn poll_next(
         mut self: Pin<&mut Self>, context: &mut std::task::Context<'_>
     ) -> std::task::Poll<Option<<Self as Stream>::Item>> {
-        if self.handle.is_none() {
-            match self.ids.pop() {
+        self.results.as_mut().poll_next(context)
+    }
+}
 
-                // If no thread exists, and there are IDs left to populate, spawn a thread to do so
-                Some(id) => {
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-                    let database = self.database.clone();
-                    let dlp_path = self.dlp_path.clone();
-                    let music_path = self.music_path.clone();
+    #[test]
+    fn classify_captured_failures() {
+        let cases = [
+            ("ERROR: unable to write data: [Errno 28] No space left on device", DownloadError::DiskFull),
+            ("ERROR: [youtube] dQw4w9WgXcQ: The uploader has not made this video available in your country", DownloadError::GeoBlocked),
+            // Geo blocking wins over the generic unavailable message it comes with
+            ("ERROR: [youtube] dQw4w9WgXcQ: Video unavailable. The uploader has not made this video available in your country", DownloadError::GeoBlocked),
+            ("ERROR: [youtube] dQw4w9WgXcQ: Video unavailable. This video has been removed by the uploader", DownloadError::Unavailable),
+            ("ERROR: [youtube] dQw4w9WgXcQ: Private video. Sign in if you've been granted access to this video", DownloadError::Unavailable),
+            ("ERROR: [youtube] dQw4w9WgXcQ: Join this channel to get access to members-only content like this video", DownloadError::Unavailable),
+            ("ERROR: Postprocessing: ffprobe and ffmpeg not found. Please install or provide the path using --ffmpeg-location", DownloadError::PostProcessing),
+            ("ERROR: [youtube] dQw4w9WgXcQ: Unable to download webpage: <urlopen error [Errno -3] Temporary failure in name resolution>", DownloadError::Network),
+            ("ERROR: [youtube] dQw4w9WgXcQ: Unable to download webpage: HTTP Error 429: Too Many Requests", DownloadError::Network),
+            ("ERROR: unable to download video data: HTTP Error 503: Service Unavailable", DownloadError::Network),
+            ("ERROR: The read operation timed out", DownloadError::Network),
+            ("ERROR: [youtube] dQw4w9WgXcQ: Sign in to confirm you’re not a bot", DownloadError::Unknown),
+            ("", DownloadError::Unknown)
+        ];
 
-                    let waker = context.waker().to_owned();
+        for (stderr, error) in cases {
+            assert_eq!(classify_failure(stderr), error, "{stderr}");
+        }
+    }
 
-                    self.handle = Some(std::thread::spawn(
-                        move || populate(waker, id, database, dlp_path, music_path)
-                    ))
-                },
+    #[test]
+    fn parse_progress_template_lines() {
+        let progress = DownloadProgress::new(String::from("dQw4w9WgXcQ"));
 
-                // If no thread exists and there are no IDs left to populate, end the stream
-                None => return std::task::Poll::Ready(None)
-            }
-        }
+        let update = progress.parse("[resonate]  42.3%|   1.21MiB/s|00:03|   3.45MiB").unwrap();
+        assert_eq!(update.yt_id, "dQw4w9WgXcQ");
+        assert_eq!(update.percent, 42.3);
+        assert_eq!(update.speed.as_deref(), Some("1.21MiB/s"));
+        assert_eq!(update.eta.as_deref(), Some("00:03"));
+        assert_eq!(update.size.as_deref(), Some("3.45MiB"));
+        assert!(!update.post_processing);
 
-        let take_song = if let Some(handle) = self.handle.as_ref() {
-            handle.is_finished()
-        } else {
-            false
-        };
+        // Before the first chunk yt-dlp knows neither speed nor time left
+        let update = progress.parse("[resonate]   0.0%|Unknown B/s|Unknown|NA").unwrap();
+        assert_eq!(update.percent, 0.0);
+        assert_eq!((update.speed, update.eta, update.size), (None, None, None));
 
-        if take_song {
-            std::task::Poll::Ready(self.handle.take().unwrap().join().ok().map(|res| res.map_err(|_| ())))
-        } else {
-            std::task::Poll::Pending
-        }
+        let update = progress.parse("[resonate] N/A%|N/A|N/A|N/A");
+        assert!(update.is_none());
+    }
+
+    #[test]
+    fn parse_post_processing_keeps_the_size() {
+        let mut progress = DownloadProgress::new(String::from("dQw4w9WgXcQ"));
+        progress.size = Some(String::from("3.45MiB"));
+
+        let update = progress.parse("[ExtractAudio] Destination: /music/dQw4w9WgXcQ.mp3").unwrap();
+        assert!(update.post_processing);
+        assert_eq!(update.percent, 100.0);
+        assert_eq!(update.size.as_deref(), Some("3.45MiB"));
+
+        assert!(progress.parse("[FixupM4a] Correcting container of \"/music/dQw4w9WgXcQ.m4a\"").unwrap().post_processing);
+    }
+
+    fn song(value: Value) -> Option<Song> {
+        song_from_json(&value)
+    }
+
+    #[test]
+    fn song_from_json_prefers_the_artist_tag() {
+        let song = song(serde_json::json!({
+            "id": "dQw4w9WgXcQ", "title": "Song", "artist": "Artist", "channel": "Channel", "uploader": "Uploader",
+            "album": "Album", "duration": 213.0
+        })).unwrap();
+        assert_eq!(song.artist, "Artist");
+        assert_eq!(song.album.as_deref(), Some("Album"));
+        assert_eq!(song.duration, Duration::from_secs(213));
+        assert!(song.music_path.is_none());
+    }
+
+    #[test]
+    fn song_from_json_falls_back_to_the_channel() {
+        let song = song(serde_json::json!({
+            "id": "dQw4w9WgXcQ", "title": "Song", "artist": "", "channel": "Channel", "uploader": "Uploader", "duration": 213
+        })).unwrap();
+        assert_eq!(song.artist, "Channel");
+        assert_eq!(song.album, None);
+    }
+
+    #[test]
+    fn song_from_json_falls_back_to_the_uploader() {
+        let song = song(serde_json::json!({
+            "id": "dQw4w9WgXcQ", "title": "Song", "uploader": "Uploader", "duration": 213
+        })).unwrap();
+        assert_eq!(song.artist, "Uploader");
+    }
+
+    #[test]
+    fn song_from_json_trims_topic_channels() {
+        let song = song(serde_json::json!({
+            "id": "dQw4w9WgXcQ", "title": "Song", "channel": "Artist - Topic", "duration": 213
+        })).unwrap();
+        assert_eq!(song.artist, "Artist");
+    }
+
+    #[test]
+    fn song_from_json_needs_an_artist_and_duration() {
+        assert!(song(serde_json::json!({ "id": "dQw4w9WgXcQ", "title": "Song", "duration": 213 })).is_none());
+        assert!(song(serde_json::json!({ "id": "dQw4w9WgXcQ", "title": "Song", "channel": "Channel" })).is_none());
+        assert!(song(serde_json::json!({ "id": "dQw4w9WgXcQ", "title": "Song", "channel": "Channel", "duration": null })).is_none());
+    }
+
+    #[test]
+    fn parse_ignores_other_output() {
+        let progress = DownloadProgress::new(String::from("dQw4w9WgXcQ"));
+        assert!(progress.parse("[youtube] dQw4w9WgXcQ: Downloading webpage").is_none());
+        assert!(progress.parse("[download] Destination: /music/dQw4w9WgXcQ.webm").is_none());
+        assert!(progress.parse("").is_none());
     }
 }
@@ -1,4 +1,6 @@
-use std::collections::HashSet;
+use std::time::Duration;
+
+use async_channel::unbounded;
 
 use iced::Element;
 use iced::futures::FutureExt;
@@ -16,6 +18,8 @@ use rust_fm::session::WebSession;
 use rust_fm::playing::NowPlaying;
 
 use crate::backend::lyrics::Lyrics;
+use crate::backend::loudness::LoudnessAnalyser;
+use crate::backend::equalizer::EqualizerPreset;
 use crate::backend::thumbnail::ThumbnailManager;
 use crate::frontend::tray::SimpleTray;
 use crate::frontend::message::Message;
@@ -26,14 +30,23 @@ use crate::frontend::pages::import_page::ImportPage;
 use crate::frontend::pages::settings_page::SettingsPage;
 use crate::frontend::pages::search_page::SearchPage;
 use crate::frontend::pages::playlists_page::PlaylistsPage;
+use crate::frontend::pages::downloads_page::DownloadsPage;
 
 use crate::backend::util::is_song_similar;
 use crate::backend::database_interface::DatabaseInterface;
 use crate::backend::audio::AudioTask;
 use crate::backend::audio::ProgressUpdate;
 use crate::backend::audio::QueueFramework;
+use crate::backend::audio::QueueStartup;
 use crate::backend::audio::ScrobbleRequest;
+use crate::backend::visualiser::VisualUpdate;
+use crate::backend::waveform::Waveform;
+use crate::backend::waveform::load_waveform;
+use crate::backend::looper::Section;
 use crate::backend::filemanager::install_dlp;
+use crate::backend::filemanager::dlp_version;
+use crate::backend::filemanager::update_dlp;
+use crate::backend::filemanager::is_pinned_version;
 use crate::backend::music::Song;
 use crate::backend::settings::Secret;
 use crate::backend::rpc::RPCManager;
@@ -45,7 +58,17 @@ use crate::backend::spotify::try_auth;
 use crate::backend::spotify::load_spotify_song;
 use crate::backend::spotify::SpotifyEmmision;
 use crate::backend::web::download_song;
+use crate::backend::web::DownloadOutcome;
+use crate::backend::tagging::tag_song;
+use crate::backend::tagging::retag_library;
+use crate::backend::web::playlist_entries;
+use crate::backend::web::AsyncMetadataCollectionPool;
+use crate::backend::downloads::DownloadScheduler;
+use crate::backend::downloads::PRIORITY_NORMAL;
+use crate::backend::downloads::PRIORITY_BACKGROUND;
+use crate::backend::web::remove_partial_downloads;
 use crate::backend::filemanager::DataDir;
+use crate::backend::error::ResonateError;
 use crate::backend::database_manager::Database;
 use crate::backend::audio::AudioPlayer;
 use crate::backend::mediacontrol::MediaControl;
@@ -53,7 +76,7 @@ use crate::backend::mediacontrol::MediaControl;
 pub trait Page {
     fn update(&mut self, message: Message) -> Task<Message>;
     fn view(
-        &self, current_song_downloads: &HashSet<String>, queued_downloads: &HashSet<Song>, thumbnail_manager: &ThumbnailManager
+        &self, downloads: &DownloadScheduler, thumbnail_manager: &ThumbnailManager
     ) -> Column<'_, Message>;
     fn back(&self, previous_page: (PageType, Option<usize>)) -> (PageType, Option<usize>);
 }
@@ -70,11 +93,15 @@ pub struct Application<'a> {
     page: Box<dyn Page + 'a>,
     directories: DataDir,
     database: Database,
-    current_song_downloads: HashSet<String>,
-    download_queue: HashSet<Song>,
+    downloads: DownloadScheduler,
     audio_player: Option<AudioPlayer>,
     queue_state: Option<QueueFramework>,
     progress_state: Option<ProgressUpdate>,
+    visual_state: Option<VisualUpdate>,
+    waveform: Option<(usize, Waveform)>,      // Song id it belongs to
+    loop_start: Option<f32>,                  // A marker waiting for its B
+    sections: Vec<Section>,
+    section_name: String,
     volume: f32,
     last_page: (PageType, Option<usize>),
     current_page: (PageType, Option<usize>),
@@ -93,6 +120,11 @@ pub struct Application<'a> {
 
     current_song: Option<Song>,
     thumbnail_manager: ThumbnailManager,
+    dlp_version: Option<String>,
+    loudness_analyser: LoudnessAnalyser,
+    equalizer_presets: Vec<EqualizerPreset>,
+    equalizer_preset_name: String,
+    show_equalizer: bool,
     show_queue: bool
 }
 
@@ -109,21 +141,31 @@ impl Default for Application<'_> {
 }
 
 impl Application<'_> {
-    pub fn new(directories: DataDir, database: Database) -> Self {
+    pub fn new(mut directories: DataDir, database: Database) -> Self {
         println!("NEW RUNNING");
-        let (dlp, thumb) = (directories.get_dlp_ref().expect("DLP not installed"), directories.get_thumbnails_ref());
+        let settings = Settings::load(directories.get_root_ref());
+        directories.locate_tools(&settings);
+
+        // Without yt-dlp searching, downloads and thumbnails wait until it is installed
+        let thumbnail_manager = ThumbnailManager::new(directories.get_dlp_ref(), directories.get_thumbnails_ref());
+        let loudness_analyser = LoudnessAnalyser::new(database.derive(), directories.get_music_ref().to_path_buf());
+        remove_partial_downloads(directories.get_music_ref());
 
         Self {
             current_song: None,
-            settings: Settings::default(),
+            downloads: DownloadScheduler::new(database.derive(), settings.max_download_concurrency),
+            settings,
             page: Box::new(PlaylistsPage::new(database.derive())),
             directories: directories.clone(),
             database,
-            current_song_downloads: HashSet::new(),
-            download_queue: HashSet::new(),
             audio_player: None,
             queue_state: None,
             progress_state: None,
+            visual_state: None,
+            waveform: None,
+            loop_start: None,
+            sections: Vec::new(),
+            section_name: String::new(),
             volume: 1f32,
             last_page: (PageType::Playlists, None),
             current_page: (PageType::Playlists, None),
@@ -138,7 +180,12 @@ impl Application<'_> {
             lyrics_backend: Lyrics::new(),
             lyrics: None,
             mode: Mode::Normal,
-            thumbnail_manager: ThumbnailManager::new(dlp, thumb),
+            thumbnail_manager,
+            dlp_version: None,
+            loudness_analyser,
+            equalizer_presets: EqualizerPreset::builtins(),
+            equalizer_preset_name: String::new(),
+            show_equalizer: false,
             show_queue: true
         }
     }
@@ -153,7 +200,14 @@ impl Application<'_> {
             self.page.back(self.last_page.clone()),
             self.progress_state,
             self.volume,
+            (self.settings.speed, self.settings.preserve_pitch),
             &self.default_queue,
+            match self.show_equalizer {
+                true => Some(ResonateWidget::equalizer_panel(
+                    &self.settings.equalizer, &self.equalizer_presets, &self.equalizer_preset_name
+                )),
+                false => None
+            },
 
             Column::new().spacing(20).push(
                 Row::new().spacing(20).push(
@@ -161,7 +215,7 @@ impl Application<'_> {
                         .push(
                             match self.mode {
                                 Mode::Normal => self.page.view(
-                                    &self.current_song_downloads, &self.download_queue, &self.thumbnail_manager
+                                    &self.downloads, &self.thumbnail_manager
                                 ),
                                 Mode::Lyrics => Column::new().push(match self.lyrics.as_ref() {
                                     Some(lyrics) => ResonateWidget::lyrics(lyrics),
@@ -170,10 +224,18 @@ impl Application<'_> {
                                 }),
                                 Mode::Current => match self.current_song.as_ref() {
                                     Some(song) => ResonateWidget::now_playing_view(
-                                        &self.thumbnail_manager, song, self.progress_state
-                                    ),
+                                        &self.thumbnail_manager, song, self.progress_state, self.visual_state.as_ref(),
+                                        self.waveform.as_ref()
+                                            .filter(|(song_id, _)| *song_id == song.id)
+                                            .map(|(_, waveform)| waveform)
+                                    ).push(ResonateWidget::loop_panel(
+                                        self.queue_state.as_ref().and_then(|queue| queue.loop_section),
+                                        self.loop_start,
+                                        &self.sections,
+                                        &self.section_name
+                                    )),
                                     None => self.page.view(
-                                        &self.current_song_downloads, &self.download_queue, &self.thumbnail_manager
+                                        &self.downloads, &self.thumbnail_manager
                                     )
                                 }
                             }
@@ -208,7 +270,110 @@ impl Application<'_> {
             Message::SetNewSong(song) => {
                 self.current_song = Some(song.clone());
                 self.lyrics = None;
-                Message::Lyrics(super::message::lyric::LyricMsg::RequestLyrics(song)).task()
+                self.visual_state = None;
+                self.loop_start = None;
+                self.sections.clear();
+                let song_id = song.id;
+                Task::batch([
+                    Task::future(load_waveform(song.clone(), self.directories.get_waveforms_ref().to_path_buf()))
+                        .map(move |waveform| Message::WaveformLoaded(song_id, waveform)),
+                    Task::future(DatabaseInterface::select_sections(self.database.derive(), song_id))
+                        .map(move |sections| Message::SectionsLoaded(song_id, sections)),
+                    Message::Lyrics(super::message::lyric::LyricMsg::RequestLyrics(song)).task()
+                ])
+            }
+
+            Message::WaveformLoaded(song_id, waveform) => {
+                self.waveform = waveform.map(|waveform| (song_id, waveform));
+                Task::none()
+            }
+
+            Message::VisualUpdate(update) => {
+                self.visual_state = Some(update);
+                Task::none()
+            }
+
+            Message::SetLoopStart => {
+                let current = match self.progress_state {
+                    Some(ProgressUpdate::Seconds(current, _)) => current,
+                    _ => return Task::none()
+                };
+
+                // Moving A on an active loop keeps B where it is
+                match self.queue_state.as_ref().and_then(|queue| queue.loop_section) {
+                    Some((_, end)) if current < end => {
+                        self.loop_start = None;
+                        Message::AudioTask(AudioTask::SetLoop(Some((current, end)))).task()
+                    }
+                    _ => {
+                        self.loop_start = Some(current);
+                        Task::none()
+                    }
+                }
+            }
+
+            Message::SetLoopEnd => {
+                let current = match self.progress_state {
+                    Some(ProgressUpdate::Seconds(current, _)) => current,
+                    _ => return Task::none()
+                };
+
+                let start = match self.loop_start.or(self.queue_state.as_ref().and_then(|queue| queue.loop_section.map(|(start, _)| start))) {
+                    Some(start) if start < current => start,
+                    _ => return Task::none()
+                };
+
+                self.loop_start = None;
+                Message::AudioTask(AudioTask::SetLoop(Some((start, current)))).task()
+            }
+
+            Message::SectionName(name) => {
+                self.section_name = name;
+                Task::none()
+            }
+
+            Message::SaveSection => {
+                let (song, (start, end)) = match (
+                    self.current_song.as_ref(), self.queue_state.as_ref().and_then(|queue| queue.loop_section)
+                ) {
+                    (Some(song), Some(section)) => (song, section),
+                    _ => return Task::none()
+                };
+
+                let name = match self.section_name.trim() {
+                    "" => format!("Section {}", self.sections.len() + 1),
+                    name => name.to_string()
+                };
+                self.section_name.clear();
+
+                Task::future(DatabaseInterface::insert_section(
+                    self.database.derive(),
+                    Section { id: None, song_id: song.id, name, start, end }
+                )).map(|section| match section {
+                    Some(section) => Message::SectionSaved(section),
+                    None => Message::None
+                })
+            }
+
+            Message::SectionSaved(section) => {
+                if self.current_song.as_ref().map(|song| song.id) == Some(section.song_id) {
+                    self.sections.push(section);
+                    self.sections.sort_by(|a, b| a.start.total_cmp(&b.start));
+                }
+                Task::none()
+            }
+
+            Message::SectionsLoaded(song_id, sections) => {
+                if self.current_song.as_ref().map(|song| song.id) == Some(song_id) {
+                    self.sections = sections;
+                }
+                Task::none()
+            }
+
+            Message::DeleteSection(id) => {
+                DatabaseInterface::delete_section(self.database.derive(), id);
+                self.sections.retain(|section| section.id != Some(id));
+                Task::none()
             }
 
             Message::Lyrics(lyric_msg) => {
@@ -239,7 +404,91 @@ impl Application<'_> {
             }
 
             Message::MakeTables => {
-                Task::future(DatabaseInterface::create_tables(self.database.derive())).map(|_| Message::None)
+                // Analysis has to wait for the tables, otherwise the first backfill finds nothing
+                let database = self.database.derive();
+                let music_path = self.directories.get_music_ref().to_path_buf();
+                Task::future(DatabaseInterface::create_tables(self.database.derive(), music_path.clone())).then(move |_| Task::batch([
+                    Message::AnalyseLoudness.task(),
+                    Task::future(DatabaseInterface::select_all_equalizer_presets(database.clone()))
+                        .map(Message::EqualizerPresetsLoaded),
+                    Task::future(DatabaseInterface::select_downloads(database.clone(), music_path.clone()))
+                        .map(Message::DownloadsLoaded)
+                ]))
+            }
+
+            Message::AnalyseLoudness => {
+                self.loudness_analyser.backfill();
+                Task::none()
+            }
+
+            Message::SetNormalisation(normalisation) => {
+                self.settings.normalisation = normalisation;
+                self.settings.save(self.directories.get_root_ref());
+                let _ = self.page.update(Message::SetNormalisation(normalisation));
+                Message::AudioTask(AudioTask::SetNormalisation(normalisation)).task()
+            }
+
+            Message::ToggleEqualizer(v) => {
+                self.show_equalizer = v;
+                Task::none()
+            }
+
+            Message::SetEqualizerBand(band, gain) => {
+                if let Some(value) = self.settings.equalizer.get_mut(band) {
+                    *value = gain;
+                }
+                Message::AudioTask(AudioTask::SetEqualizer(self.settings.equalizer)).task()
+            }
+
+            Message::SaveSettings => {
+                self.settings.save(self.directories.get_root_ref());
+                Task::none()
+            }
+
+            Message::ResetSpeed => {
+                Message::AudioTask(AudioTask::SetSpeed(1f32)).task().chain(Message::SaveSettings.task())
+            }
+
+            Message::LoadEqualizerPreset(preset) => {
+                self.settings.equalizer = preset.gains;
+                self.settings.save(self.directories.get_root_ref());
+                Message::AudioTask(AudioTask::SetEqualizer(preset.gains)).task()
+            }
+
+            Message::EqualizerPresetName(name) => {
+                self.equalizer_preset_name = name;
+                Task::none()
+            }
+
+            Message::SaveEqualizerPreset => {
+                let name = self.equalizer_preset_name.trim().to_string();
+                if name.is_empty() { return Task::none(); }
+                self.equalizer_preset_name.clear();
+
+                Task::future(DatabaseInterface::insert_equalizer_preset(
+                    self.database.derive(),
+                    EqualizerPreset { id: None, name, gains: self.settings.equalizer }
+                )).map(|preset| match preset {
+                    Some(preset) => Message::EqualizerPresetSaved(preset),
+                    None => Message::None
+                })
+            }
+
+            Message::EqualizerPresetSaved(preset) => {
+                self.equalizer_presets.push(preset);
+                Task::none()
+            }
+
+            Message::EqualizerPresetsLoaded(presets) => {
+                self.equalizer_presets = EqualizerPreset::builtins();
+                self.equalizer_presets.extend(presets);
+                Task::none()
+            }
+
+            Message::DeleteEqualizerPreset(id) => {
+                DatabaseInterface::delete_equalizer_preset(self.database.derive(), id);
+                self.equalizer_presets.retain(|preset| preset.id != Some(id));
+                Task::none()
             }
 
             Message::StartTray => {
@@ -249,7 +498,11 @@ impl Application<'_> {
                             receiver,
                             |msg| match msg {
                                 super::tray::TrayMessage::OpenMain => Some(Message::OpenMain),
-                                super::tray::TrayMessage::Quit => Some(Message::Quit)
+                                super::tray::TrayMessage::Quit => Some(Message::Quit),
+                                super::tray::TrayMessage::ToggleStopAfterCurrent => Some(Message::ToggleStopAfterCurrent),
+                                super::tray::TrayMessage::SleepTimer(minutes) => Some(Message::AudioTask(
+                                    AudioTask::SetSleepTimer(minutes.map(|minutes| Duration::from_secs(minutes * 60)))
+                                ))
                             }
                         )
                     ),
@@ -258,6 +511,11 @@ impl Application<'_> {
             }
 
             Message::Quit => {
+                // Running downloads stay in the queue and start again next launch
+                self.downloads.pause();
+                if let Some(audio_player) = self.audio_player.take() {
+                    audio_player.shutdown();
+                }
                 iced::exit()
             }
 
@@ -268,7 +526,10 @@ impl Application<'_> {
             Message::DownloadDLP => {
                 println!("[UPDATE] Check if DLP is downloaded");
                 match self.directories.get_dlp_ref() {
-                    Some(_) => { println!("ALREADY DOWNLOADED!"); Task::none() }
+                    Some(dlp_path) => {
+                        println!("ALREADY DOWNLOADED!");
+                        Message::DLPDownloaded(Some(dlp_path.to_path_buf())).task()
+                    }
                     None => Task::future(
                         install_dlp(self.directories.get_dependencies_ref().to_path_buf())
                             .map(|res| Message::DLPDownloaded(res.ok()))
@@ -277,63 +538,145 @@ impl Application<'_> {
             }
 
             Message::DLPDownloaded(dlp_path) => {
-                if let Some(dlp_path) = dlp_path {
-                    self.directories.take_dlp_path(dlp_path)
-                }
+                let version = match dlp_path {
+                    Some(dlp_path) => {
+                        if self.directories.get_dlp_ref() != Some(dlp_path.as_path()) {
+                            self.directories.take_dlp_path(dlp_path.clone());
+                        }
+                        self.thumbnail_manager.set_dlp_path(dlp_path.clone());
+                        Task::future(dlp_version(dlp_path)).map(Message::DLPVersion)
+                    }
+                    None => Task::none()
+                };
 
-                Task::none()
+                // Anything restored before yt-dlp arrived has been waiting for it
+                Task::batch(vec![version, self.start_downloads()])
             }
 
-            Message::Download(song) => {
+            Message::DLPVersion(version) => {
+                self.dlp_version = version.clone();
+                let _ = self.page.update(Message::DLPVersion(version.clone()));
 
-                if let Some(mp) = song.music_path.as_ref() {
-                    if mp.exists() {
-                        return Task::none();
+                // Move onto the pinned version if something else is installed
+                match (self.settings.dlp_version.as_ref(), version) {
+                    (Some(pin), Some(version)) if self.directories.is_dlp_managed() && !is_pinned_version(pin, &version) => {
+                        Message::UpdateDLP.task()
                     }
+                    _ => Task::none()
                 }
+            }
 
-                if self.current_song_downloads.contains(&song.yt_id) {
-                    return Task::none();
+            Message::UpdateDLP => {
+                let _ = self.page.update(Message::UpdateDLP);
+                match self.directories.get_dlp_ref().filter(|_| self.directories.is_dlp_managed()) {
+                    Some(dlp_path) => Task::future(
+                        update_dlp(dlp_path.to_path_buf(), self.settings.dlp_version.clone())
+                    ).map(Message::DLPUpdated),
+                    None => Message::DLPUpdated(Err(ResonateError::ExecNotFound)).task()
                 }
+            }
 
-                let is_space: bool = self.current_song_downloads.len() > self.settings.max_download_concurrency;
-
-                if self.download_queue.contains(&song) && !is_space {
-                    return Task::none();
+            Message::DLPUpdated(res) => {
+                if let Ok(version) = res.as_ref() {
+                    self.dlp_version = Some(version.clone());
                 }
+                self.page.update(Message::DLPUpdated(res))
+            }
 
-                if is_space {
-                    self.download_queue.insert(song.clone());
-                    Task::none()
-                } else {
-                    let _ = self.download_queue.remove(&song);
-                    self.current_song_downloads.insert(song.yt_id.clone());
-                    Task::future(
-                        download_song(
-                            self.directories.get_dlp_ref().map(|x| x.to_path_buf()),
-                            self.directories.get_music_ref().to_path_buf(),
-                            song
-                        )
-                    ).map(move |res| match res {
-                        Ok(song) => Message::SongDownloaded(song),
-                        Err(song) => Message::DownloadFailed(song)
-                    })
+            Message::SetDLPPin(pin) => {
+                let pin = pin.trim().to_string();
+                self.settings.dlp_version = match pin.is_empty() {
+                    true => None,
+                    false => Some(pin)
+                };
+                self.settings.save(self.directories.get_root_ref());
+                self.page.update(Message::SetDLPPin(self.settings.dlp_version.clone().unwrap_or_default()))
+            }
+
+            Message::Download(song) => {
+                match self.downloads.enqueue(song, PRIORITY_NORMAL) {
+                    true => self.start_downloads(),
+                    false => Task::none()
                 }
             }
 
+            Message::DownloadsLoaded(entries) => {
+                self.downloads.restore(entries);
+                self.start_downloads()
+            }
+
+            Message::PauseDownloads => {
+                self.downloads.pause();
+                Task::none()
+            }
+
+            Message::ResumeDownloads => {
+                self.downloads.resume();
+                self.start_downloads()
+            }
+
+            Message::RetryFailedDownloads => {
+                self.downloads.retry_failed();
+                self.start_downloads()
+            }
+
+            Message::ClearCompletedDownloads => {
+                self.downloads.clear_completed();
+                Task::none()
+            }
+
+            Message::SetDownloadConcurrency(max_concurrency) => {
+                self.settings.max_download_concurrency = max_concurrency;
+                self.settings.save(self.directories.get_root_ref());
+                self.downloads.set_max_concurrency(max_concurrency);
+                self.start_downloads()
+            }
+
+            Message::CancelDownload(song) => {
+                self.downloads.cancel(&song.yt_id);
+                Task::none()
+            }
+
+            Message::DownloadProgress(progress) => {
+                self.downloads.update_progress(progress);
+                Task::none()
+            }
+
             Message::SongDownloaded(song) => {
-                self.current_song_downloads.remove(&song.yt_id);
-                let _ = self.page.update(Message::SongDownloaded(song));
-
-                if !self.download_queue.is_empty() {
-                    let song = match self.download_queue.iter().nth(0) {
-                        Some(song) => song.clone(),
-                        None => return Task::none()
-                    };
-                    let _ = self.download_queue.remove(&song);
-                    Message::Download(song).task()
-                } else {
-                    Task::none()
+                self.downloads.finished(&song);
+                self.loudness_analyser.send(song.clone());
+                let _ = self.page.update(Message::SongDownloaded(song.clone()));
+                self.start_downloads()
+            }
+
+            Message::TagSong(song) => {
+                // Tagging replaces the file, so nothing may open it until this is done, whether it worked or not
+                let ffmpeg_path = match self.directories.get_ffmpeg_ref() {
+                    Some(ffmpeg_path) => ffmpeg_path.to_path_buf(),
+                    None => return Message::SongDownloaded(song).task()
+                };
+
+                // Make sure the cover is on disk first. This finishes straight away if it already is.
+                let cover = self.thumbnail_manager.download_thumbnail(song.clone());
+                let thumbnail_dir = self.directories.get_thumbnails_ref().to_path_buf();
+
+                Task::future(async move {
+                    let _ = cover.await;
+                    let _ = tag_song(ffmpeg_path, thumbnail_dir, song.clone()).await;
+                    Message::SongDownloaded(song)
+                })
+            }
+
+            Message::RetagLibrary => {
+                let _ = self.page.update(Message::RetagLibrary);
+                match self.directories.get_ffmpeg_ref() {
+                    Some(ffmpeg_path) => Task::future(retag_library(
+                        self.database.derive(),
+                        ffmpeg_path.to_path_buf(),
+                        self.directories.get_music_ref().to_path_buf(),
+                        self.directories.get_thumbnails_ref().to_path_buf()
+                    )).map(|(tagged, total)| Message::LibraryRetagged(tagged, total)),
+                    None => Message::LibraryRetagged(0, 0).task()
                 }
             }
 
@@ -453,16 +796,34 @@ impl Application<'_> {
             }
 
             Message::AudioTask(task) => {
-                if let AudioTask::SetVolume(v) = task { self.volume = v; }
+                match task {
+                    AudioTask::SetVolume(v) => self.volume = v,
+                    AudioTask::SetSpeed(speed) => self.settings.speed = speed,
+                    AudioTask::SetPreservePitch(preserve_pitch) => {
+                        self.settings.preserve_pitch = preserve_pitch;
+                        self.settings.save(self.directories.get_root_ref());
+                    }
+                    AudioTask::SetAutoplay(autoplay) => {
+                        self.settings.autoplay = autoplay;
+                        self.settings.save(self.directories.get_root_ref());
+                    }
+                    _ => {}
+                }
                 if let Some(ap) = self.audio_player.as_ref() { let _ = ap.send_task(task); }
                 Task::none()
             }
 
             Message::QueueUpdate(queue_state) => {
+                self.tray.sync(&queue_state);
                 self.queue_state = Some(queue_state);
                 Task::none()
             }
 
+            Message::ToggleStopAfterCurrent => {
+                let stop_after_current = self.queue_state.as_ref().is_some_and(|queue_state| queue_state.stop_after_current);
+                Message::AudioTask(AudioTask::SetStopAfterCurrent(!stop_after_current)).task()
+            }
+
             Message::LoadEverythingIntoQueue => {
                 Task::stream(
                     Relay::consume_receiver(
@@ -479,13 +840,16 @@ impl Application<'_> {
             }
             
             Message::LoadAudio => {
-                let (audio_player, queue_receiver, progress_receiver, scrobble_receiver) = match AudioPlayer::new() {
+                let (audio_player, queue_receiver, progress_receiver, scrobble_receiver, visual_receiver) = match AudioPlayer::new(
+                    self.database.derive(), self.directories.get_music_ref().to_path_buf(), &self.settings
+                ) {
                     Ok(data) => data,
                     Err(_) => return Task::none()
                 };
 
                 self.audio_player = Some(audio_player);
                 Task::batch(vec![
+                    Message::RestoreQueue.task(),
                     Task::stream(
                         Relay::consume_receiver(
                             queue_receiver, |message| Some(Message::QueueUpdate(message))
@@ -500,10 +864,38 @@ impl Application<'_> {
                         Relay::consume_receiver(
                             scrobble_receiver, |message| Some(Message::ScrobbleRequest(message))
                         )
+                    ),
+                    Task::stream(
+                        Relay::consume_receiver(
+                            visual_receiver, |message| Some(Message::VisualUpdate(message))
+                        )
                     )
                 ])
             }
 
+            Message::RestoreQueue => match self.settings.queue_startup {
+                QueueStartup::Empty => Task::none(),
+                QueueStartup::Library => Message::LoadEverythingIntoQueue.task(),
+                QueueStartup::Restore => Task::future(DatabaseInterface::select_queue(
+                    self.database.derive(), self.directories.get_music_ref().to_path_buf()
+                )).map(|snapshot| match snapshot {
+                    Some(snapshot) => Message::AudioTask(AudioTask::Restore(snapshot)),
+                    None => Message::None
+                })
+            }
+
+            Message::SetQueueStartup(queue_startup) => {
+                self.settings.queue_startup = queue_startup;
+                self.settings.save(self.directories.get_root_ref());
+                self.page.update(Message::SetQueueStartup(queue_startup))
+            }
+
+            Message::SetDownloadFormat(format) => {
+                self.settings.download_format = format;
+                self.settings.save(self.directories.get_root_ref());
+                self.page.update(Message::SetDownloadFormat(format))
+            }
+
             Message::RowIntoSongForQueue(row) => {
                 Task::future(DatabaseInterface::construct_song(row, self.directories.get_music_ref().to_path_buf()))
                     .map(|option| match option {
@@ -549,9 +941,14 @@ impl Application<'_> {
                 })
             }
 
-            Message::LoadEntirePlaylist(playlist_id, _) => {
+            Message::LoadEntirePlaylist(playlist_id, shuffle) => {
                 let receiver = DatabaseInterface::select_all_songs_in_playlist(self.database.derive(), playlist_id);
-                Task::stream(Relay::consume_receiver(receiver,
+                // Shuffle has to be on before the songs arrive so each one lands in a random spot
+                let shuffle = match shuffle {
+                    true => Message::AudioTask(AudioTask::SetShuffle(true)).task(),
+                    false => Task::none()
+                };
+                shuffle.chain(Task::stream(Relay::consume_receiver(receiver,
                     |item_stream| match item_stream {
                         crate::backend::database_manager::ItemStream::End => None,
                         crate::backend::database_manager::ItemStream::Error => None,
@@ -559,10 +956,12 @@ impl Application<'_> {
                             Message::RowIntoSongForQueue(row)
                         )
                    }
-                ))
+                )))
             }
 
             Message::RemoveSongFromPlaylist(song_id, playlist_id) => {
+                self.downloads.cancel_song(song_id);
+
                 DatabaseInterface::remove_song_from_playlist(self.database.derive(), song_id, playlist_id);
                 let _ = self.page.update(Message::RemoveSongFromPlaylist(song_id, playlist_id));
                 Message::AudioTask(AudioTask::RemoveSongById(song_id)).task()
@@ -574,21 +973,16 @@ impl Application<'_> {
                 Task::none()
             }
 
-            Message::DownloadFailed(song) => {
-                println!("[UPDATE] Download of {} failed.", song.title);
-                self.current_song_downloads.remove(&song.yt_id);
-                let _ = self.page.update(Message::DownloadFailed(song));
+            Message::DownloadFailed(song, error) => {
+                println!("[UPDATE] Download of {} failed: {}", song.title, error.as_str());
+                self.downloads.failed(&song, error);
+                let _ = self.page.update(Message::DownloadFailed(song, error));
+                self.start_downloads()
+            }
 
-                if !self.download_queue.is_empty() {
-                    let song = match self.download_queue.iter().nth(0) {
-                        Some(song) => song.clone(),
-                        None => return Task::none()
-                    };
-                    let _ = self.download_queue.remove(&song);
-                    Message::Download(song).task()
-                } else {
-                    Task::none()
-                }
+            Message::DownloadStopped(song, request) => {
+                self.downloads.stopped(&song, request);
+                self.start_downloads()
             }
 
             Message::ProgressUpdate(update) => {
@@ -656,6 +1050,31 @@ impl Application<'_> {
                 }
             }
 
+            Message::YouTubePlaylist(url) => {
+                let _ = self.page.update(Message::YouTubePlaylist(String::new()));
+
+                let dlp_path = match self.directories.get_dlp_ref() {
+                    Some(dlp_path) => dlp_path.to_path_buf(),
+                    None => return Message::YouTubePlaylistFailed.task()
+                };
+
+                let database = self.database.derive();
+                let music_path = self.directories.get_music_ref().to_path_buf();
+
+                Task::future(playlist_entries(dlp_path.clone(), url)).then(move |res| match res {
+                    // Name first, so the page knows how many songs to wait for
+                    Ok((name, hits)) => Message::SpotifyPlaylistName(name, hits.len()).task().chain(
+                        Task::stream(AsyncMetadataCollectionPool::import(
+                            database.clone(), hits, dlp_path.clone(), music_path.clone()
+                        )).map(|res| match res {
+                            Ok(song) => Message::SearchResult(song, true),
+                            Err(_) => Message::ImportEntrySkipped
+                        })
+                    ),
+                    Err(_) => Message::YouTubePlaylistFailed.task()
+                })
+            }
+
             Message::GetSongByTitleForSpotify(option, track) => {
                 match option {
                     Some(song) => Message::SearchResult(song, true).task(),
@@ -685,8 +1104,7 @@ impl Application<'_> {
                         load_spotify_song(
                             track,
                             dlp_path.to_path_buf(),
-                            self.database.derive(),
-                            self.directories.get_music_ref().to_path_buf()
+                            self.database.derive()
                         )
                     ).map(|res| match res {
                         Ok(song) => Message::SearchResult(song, true),
@@ -696,15 +1114,11 @@ impl Application<'_> {
                 }
             }
 
-            Message::DownloadAll(mut songs) => {
-                if songs.is_empty() { return Task::none(); }
-                let mut task = Message::Download(songs.remove(0)).task();
-                songs.reverse();
-
-                while let Some(song) = songs.pop() {
-                   task = task.chain(Message::Download(song).task());
+            Message::DownloadAll(songs) => {
+                for song in songs {
+                    self.downloads.enqueue(song, PRIORITY_BACKGROUND);
                 }
-                task
+                self.start_downloads()
             }
 
             Message::LoadSecrets => {
@@ -948,6 +1362,41 @@ impl Application<'_> {
         }
     }
 
+    /// Start whatever the scheduler has room for. Queued downloads wait until yt-dlp is available.
+    fn start_downloads(&mut self) -> Task<Message> {
+        let dlp_path = match self.directories.get_dlp_ref() {
+            Some(dlp_path) => dlp_path.to_path_buf(),
+            None => return Task::none()
+        };
+
+        Task::batch(self.downloads.start().into_iter().map(|(song, cancel)| {
+            // The progress stream ends by itself once the download drops its sender
+            let (progress_sender, progress_receiver) = unbounded();
+            Task::batch([
+                Task::stream(
+                    Relay::consume_receiver(
+                        progress_receiver, |progress| Some(Message::DownloadProgress(progress))
+                    )
+                ),
+                Task::future(
+                    download_song(
+                        Some(dlp_path.clone()),
+                        self.directories.get_ffmpeg_ref().map(|path| path.to_path_buf()),
+                        self.directories.get_music_ref().to_path_buf(),
+                        song,
+                        self.settings.download_format,
+                        progress_sender,
+                        cancel
+                    )
+                ).map(move |outcome| match outcome {
+                    DownloadOutcome::Finished(song) => Message::TagSong(song),
+                    DownloadOutcome::Failed(song, error) => Message::DownloadFailed(song, error),
+                    DownloadOutcome::Stopped(song, request) => Message::DownloadStopped(song, request)
+                })
+            ])
+        }).collect::<Vec<Task<Message>>>())
+    }
+
     fn load_page(&mut self, page_type: PageType, playlist_id: Option<usize>) {
         self.last_page = self.current_page.to_owned();
         self.current_page = (page_type.clone(), playlist_id);
@@ -960,7 +1409,7 @@ impl Application<'_> {
             PageType::Playlists => Box::new(PlaylistsPage::new(self.database.derive())),
 
             PageType::ViewPlaylist => Box::new(
-                match PlaylistPage::new(playlist_id, self.database.derive(), self.directories.get_music_ref().to_path_buf()) {
+                match PlaylistPage::new(playlist_id, self.database.derive()) {
                     Ok(page) => page,
                     Err(_) => return // THIS SHOULD BE AN ERROR NOTIFICATION
                 }
@@ -973,8 +1422,10 @@ impl Application<'_> {
             )),
 
             PageType::Settings => {
-                Box::new(SettingsPage::new())
+                Box::new(SettingsPage::new(&self.settings, &self.directories, self.dlp_version.clone()))
             }
+
+            PageType::Downloads => Box::new(DownloadsPage)
         };
     }
 }
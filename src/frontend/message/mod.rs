@@ -8,10 +8,21 @@ use rust_fm::auth::WebOAuth;
 
 use crate::backend::database_manager::DatabaseParam;
 use crate::backend::settings::Secret;
+use crate::backend::loudness::Normalisation;
+use crate::backend::equalizer::EqualizerPreset;
 
-use crate::backend::audio::{AudioTask, ProgressUpdate, QueueFramework, ScrobbleRequest};
-use crate::backend::music::{Playlist, Song};
+use crate::backend::audio::{AudioTask, ProgressUpdate, QueueFramework, QueueStartup, ScrobbleRequest};
+use crate::backend::music::{AudioFormat, Playlist, Song};
 use crate::backend::rpc::RPCMessage;
+use crate::backend::web::DownloadProgress;
+use crate::backend::web::SearchHit;
+use crate::backend::web::StopRequest;
+use crate::backend::error::DownloadError;
+use crate::backend::error::ResonateError;
+use crate::backend::downloads::DownloadEntry;
+use crate::backend::visualiser::VisualUpdate;
+use crate::backend::waveform::Waveform;
+use crate::backend::looper::Section;
 
 use super::application::Mode;
 
@@ -27,7 +38,7 @@ pub enum Message {
     LoadPage(PageType, Option<usize>),   // Loads a new page based on the PageType enum
     TextInput(String),                   // Primary TextInput task for single-entry pages
     SubmitSearch,                        // Primary task for single-entry-pages
-    LoadSearchResults(Vec<String>),      // Create a batch of tasks to pull down metadata for each ID and queue into search results buffer
+    LoadSearchResults(Vec<SearchHit>),   // Create a batch of tasks to pull down metadata for each ID and queue into search results buffer
     DLPWarning,                          // Notify the user that the current action requires yt-dlp.
     CollectMetadata(String),             // Task created by LoadSearchResults
     SearchResult(Song, bool),            // Final task in the search process - actually adds a finished song to the buffer
@@ -35,12 +46,21 @@ pub enum Message {
     Download(Song),                      // Download a song asynchronously. Relies on the frontend to manage concurrency
     DownloadAll(Vec<Song>),              // Downloads every single song
     SongDownloaded(Song),
+    DownloadProgress(DownloadProgress),
     CreatePlaylist,                      // Create a new "My Playlist" name playlist, adding a number if multiple exist
     StartEditing(usize),                 // Edit the name of a playlist on the Playlists page
     StopEditing,                         // Exit exit mode
     DownloadDLP,                         // Spawns a task to check if DLP is downloaded, and if it isn't, download it
     DLPDownloaded(Option<PathBuf>),      // <- Obvious
-    DownloadFailed(Song),
+    DownloadFailed(Song, DownloadError),
+    DownloadStopped(Song, StopRequest),  // Paused or cancelled, as asked
+    CancelDownload(Song),                // Stop a running download, or take it out of the queue
+    DownloadsLoaded(Vec<DownloadEntry>), // The download queue saved last session
+    PauseDownloads,
+    ResumeDownloads,
+    RetryFailedDownloads,
+    ClearCompletedDownloads,
+    SetDownloadConcurrency(usize),
     AddSongToPlaylist(Song, usize),      // This also downloads the song
     SongAddedToPlaylist(usize),          // For updating the GUI
     RemoveSongFromPlaylist(usize, usize),// Song id, playlist id
@@ -63,6 +83,9 @@ pub enum Message {
     SpotifyInvalidID,
     SpotifyAuthenticationSuccess,
     SpotifyAuthenticationFailedAgain,
+    YouTubePlaylist(String),             // Playlist, album or mix link, listed through yt-dlp
+    YouTubePlaylistFailed,
+    ImportEntrySkipped,                  // An entry of the playlist being imported couldn't be made into a song
     LoadSecrets,
     ChangeSecret(Secret),
     SaveSecret(Secret),
@@ -98,7 +121,46 @@ pub enum Message {
     SetNewSong(Song),
     RequestThumbnail(Song),
     ThumbnailDownloaded(Song),
-    ToggleQueue(bool)
+    ToggleQueue(bool),
+    AnalyseLoudness,                     // Measure the loudness of every downloaded song that hasn't been yet
+    SetNormalisation(Normalisation),
+
+    ToggleEqualizer(bool),
+    SetEqualizerBand(usize, f32),        // Band index and gain in dB, applied to the playing song immediately
+    SaveSettings,
+    ResetSpeed,                          // Back to 1x, saved like a slider release
+    LoadEqualizerPreset(EqualizerPreset),
+    EqualizerPresetName(String),
+    SaveEqualizerPreset,
+    EqualizerPresetSaved(EqualizerPreset),
+    EqualizerPresetsLoaded(Vec<EqualizerPreset>),
+    DeleteEqualizerPreset(usize),
+
+    ToggleStopAfterCurrent,
+    RestoreQueue,                        // Fill the queue according to the startup setting
+    SetQueueStartup(QueueStartup),
+
+    SetDownloadFormat(AudioFormat),
+
+    DLPVersion(Option<String>),          // Reported by the installed yt-dlp, None if it won't run
+    UpdateDLP,                           // To the pinned version if there is one, otherwise the latest
+    DLPUpdated(Result<String, ResonateError>),
+    SetDLPPin(String),                   // Empty to follow the latest release
+
+    TagSong(Song),                       // Write its metadata and cover into a finished download, then report it downloaded
+    RetagLibrary,
+    LibraryRetagged(usize, usize),       // Tagged, out of
+
+    VisualUpdate(VisualUpdate),
+    WaveformLoaded(usize, Option<Waveform>),
+
+    SetLoopStart,                        // Marker at the current position
+    SetLoopEnd,
+    SectionName(String),
+    SaveSection,
+    SectionSaved(Section),
+    SectionsLoaded(usize, Vec<Section>), // Song id they belong to
+    DeleteSection(usize)
 }
 
 #[derive(Clone, Debug, PartialEq, Eq)]
@@ -107,7 +169,8 @@ pub enum PageType {
     Playlists,
     ViewPlaylist,
     ImportSpotify,
-    Settings
+    Settings,
+    Downloads
 }
 
 impl Message {
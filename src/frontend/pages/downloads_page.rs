@@ -0,0 +1,99 @@
+use iced::alignment::Vertical;
+use iced::widget::Column;
+use iced::widget::Row;
+use iced::widget::text;
+use iced::widget::horizontal_space;
+use iced::Task;
+
+use crate::backend::downloads::DownloadScheduler;
+use crate::backend::downloads::DownloadState;
+use crate::backend::thumbnail::ThumbnailManager;
+use crate::frontend::application::Page;
+use crate::frontend::message::Message;
+use crate::frontend::message::PageType;
+use crate::frontend::widgets::ResonateWidget;
+use crate::frontend::widgets::ResonateColour;
+
+/// Most downloads allowed to run at once from this page
+const MAX_CONCURRENCY: usize = 8;
+
+/// Everything the scheduler knows about, grouped by state
+pub struct DownloadsPage;
+
+impl Page for DownloadsPage {
+    fn view(&self, downloads: &DownloadScheduler, _: &ThumbnailManager) -> Column<'_, Message> {
+        let entries = downloads.entries();
+        let concurrency = downloads.max_concurrency();
+        let any_failed = entries.iter().any(|entry| matches!(entry.state, DownloadState::Failed(_)));
+        let any_done = entries.iter().any(|entry| entry.state == DownloadState::Done);
+
+        let controls = Row::new().spacing(10).align_y(Vertical::Center)
+            .push(
+                match downloads.is_paused() {
+                    true => ResonateWidget::inline_button("RESUME ALL").on_press(Message::ResumeDownloads),
+                    false => ResonateWidget::inline_button("PAUSE ALL").on_press(Message::PauseDownloads)
+                }
+            ).push(
+                ResonateWidget::inline_button("RETRY FAILED").on_press_maybe(match any_failed {
+                    true => Some(Message::RetryFailedDownloads),
+                    false => None
+                })
+            ).push(
+                ResonateWidget::inline_button("CLEAR COMPLETED").on_press_maybe(match any_done {
+                    true => Some(Message::ClearCompletedDownloads),
+                    false => None
+                })
+            ).push(
+                horizontal_space()
+            ).push(
+                text("AT ONCE").size(16).color(ResonateColour::darker())
+            ).push(
+                ResonateWidget::inline_button("-").on_press_maybe(match concurrency > 1 {
+                    true => Some(Message::SetDownloadConcurrency(concurrency - 1)),
+                    false => None
+                })
+            ).push(
+                text(concurrency.to_string()).size(20).color(ResonateColour::text())
+            ).push(
+                ResonateWidget::inline_button("+").on_press_maybe(match concurrency < MAX_CONCURRENCY {
+                    true => Some(Message::SetDownloadConcurrency(concurrency + 1)),
+                    false => None
+                })
+            );
+
+        let section = |state: &DownloadState| match state {
+            DownloadState::Active => 0,
+            DownloadState::Queued => 1,
+            DownloadState::Failed(_) => 2,
+            DownloadState::Done => 3
+        };
+
+        let mut column = Column::new().spacing(10);
+        for (idx, label) in ["ACTIVE", "QUEUED", "FAILED", "COMPLETED"].into_iter().enumerate() {
+            let mut in_section = entries.iter().filter(|entry| section(&entry.state) == idx).peekable();
+            if in_section.peek().is_none() { continue; }
+
+            column = column.push(text(label).size(16).color(ResonateColour::darker()));
+            for entry in in_section {
+                column = column.push(ResonateWidget::download_entry(entry, downloads.progress(&entry.song.yt_id)));
+            }
+        }
+
+        if entries.is_empty() {
+            column = column.push(text("Nothing has been downloaded yet").color(ResonateColour::text()));
+        }
+
+        Column::new().spacing(20)
+            .push(ResonateWidget::header("Downloads"))
+            .push(controls)
+            .push(ResonateWidget::padded_scrollable(column.into()))
+    }
+
+    fn update(&mut self, _: Message) -> Task<Message> {
+        Task::none()
+    }
+
+    fn back(&self, previous_page: (PageType, Option<usize>)) -> (PageType, Option<usize>) {
+        previous_page
+    }
+}
@@ -1,5 +1,3 @@
-use std::collections::HashSet;
-
 use iced::alignment::Vertical;
 use iced::widget::Column;
 use iced::widget::Container;
@@ -9,7 +7,9 @@ use iced::widget::Space;
 use iced::Length;
 use iced::Task;
 
+use crate::backend::downloads::DownloadScheduler;
 use crate::backend::database_interface::DatabaseInterface;
+use crate::backend::web::is_youtube_url;
 use crate::backend::music::Playlist;
 use crate::backend::thumbnail::ThumbnailManager;
 use crate::frontend::application::Page;
@@ -29,6 +29,7 @@ pub enum SpotifyNotification {
     NotAuthenticated,
     NoIdOrSecret,
     InvalidID,
+    NotFound,
     Success
 }
 
@@ -64,25 +65,37 @@ impl ImportPage {
             failed_again: false
         }
     }
+
+    fn update_progress(&mut self) {
+        self.notification = Some(SpotifyNotification::Waiting(self.songs.len()));
+
+        if let Some(size) = self.playlist_size {
+            if size == self.songs.len() {
+                self.notification = Some(SpotifyNotification::Finished)
+            }
+        }
+    }
 }
 
 impl Page for ImportPage {
     fn view(
-        &self, current_song_downloads: &HashSet<String>, download_queue: &HashSet<Song>, thumbnail_manager: &ThumbnailManager
+        &self, downloads: &DownloadScheduler, thumbnail_manager: &ThumbnailManager
     ) -> Column<'_, Message> {
 
         let mut column = Column::new().spacing(20);
 
         for song in &self.songs {
 
-            let is_downloading = current_song_downloads.contains(&song.yt_id);
-            let is_queued = download_queue.contains(song);
+            let downloading = downloads.progress(&song.yt_id);
+            let is_queued = downloads.is_queued(&song.yt_id);
+            let failed = downloads.error(&song.yt_id);
 
             let widget = ResonateWidget::song(
                 song,
                 thumbnail_manager,
-                is_downloading,
+                downloading,
                 is_queued,
+                failed,
                 None,
                 false
             );
@@ -149,6 +162,19 @@ impl Page for ImportPage {
                                     )
                             )
                     }
+                    SpotifyNotification::NotFound => {
+                        Row::new().padding(10).align_y(Vertical::Center)
+                            .push(
+                                text("Couldn't read that playlist").size(25).color(ResonateColour::red())
+                            ).push(
+                                Row::new().spacing(20).width(Length::Fill)
+                                    .push(Space::new(Length::Fill, Length::Fixed(32f32)))
+                                    .push(
+                                        ResonateWidget::button_widget(crate::frontend::assets::close())
+                                            .on_press(Message::ClearNotification)
+                                    )
+                            )
+                    }
                     SpotifyNotification::Finished => {
                         Row::new().padding(10).align_y(Vertical::Center)
                             .push(
@@ -195,7 +221,7 @@ impl Page for ImportPage {
             );
 
         Column::new().spacing(20)
-            .push(ResonateWidget::header("Spotify Playlist Import"))
+            .push(ResonateWidget::header("Playlist Import"))
             .push_maybe(
                 self.playlist_name.as_ref().map(|name| ResonateWidget::header(name))
             )
@@ -204,10 +230,13 @@ impl Page for ImportPage {
             .push(
                 Row::new().spacing(20).align_y(Vertical::Center)
                     .push(
-                        ResonateWidget::search_bar("Enter share link...", &self.input)
+                        ResonateWidget::search_bar("Enter Spotify or YouTube link...", &self.input)
                             .on_paste(Message::TextInput)
                             .on_input(Message::TextInput)
-                            .on_submit(Message::SpotifyPlaylist(self.input.clone()))
+                            .on_submit(match is_youtube_url(&self.input) {
+                                true => Message::YouTubePlaylist(self.input.clone()),
+                                false => Message::SpotifyPlaylist(self.input.clone())
+                            })
                     ).push_maybe(
                         if self.saved {
                             None
@@ -230,19 +259,24 @@ impl Page for ImportPage {
         match message {
             Message::SearchResult(song, _) => {
                 self.songs.push(song);
-                self.notification = Some(SpotifyNotification::Waiting(self.songs.len()));
-                
-                if let Some(size) = self.playlist_size {
-                    if size == self.songs.len() {
-                        self.notification = Some(SpotifyNotification::Finished)
-                    }
-                }
+                self.update_progress();
             },
 
-            Message::SpotifyPlaylist(_) => {
+            Message::ImportEntrySkipped => {
+                self.playlist_size = self.playlist_size.map(|size| size.saturating_sub(1));
+                self.update_progress();
+            }
+
+            Message::SpotifyPlaylist(_) | Message::YouTubePlaylist(_) => {
                 self.input.clear();
                 self.songs.clear();
                 self.saved = false;
+                self.playlist_name = None;
+                self.playlist_size = None;
+            }
+
+            Message::YouTubePlaylistFailed => {
+                self.notification = Some(SpotifyNotification::NotFound);
             }
 
             Message::SpotifyAuthenticationFailedAgain => {
@@ -3,3 +3,4 @@ pub mod playlists_page;
 pub mod playlist_page;
 pub mod search_page;
 pub mod settings_page;
+pub mod downloads_page;
@@ -1,6 +1,3 @@
-use std::collections::HashSet;
-use std::path::PathBuf;
-
 use iced::alignment::Vertical;
 use iced::widget::Column;
 use iced::widget::Container;
@@ -9,6 +6,7 @@ use iced::widget::text;
 use iced::Length;
 use iced::Task;
 
+use crate::backend::downloads::DownloadScheduler;
 use crate::backend::database_interface::DatabaseInterface;
 use crate::backend::music;
 use crate::backend::thumbnail::ThumbnailManager;
@@ -32,12 +30,11 @@ pub struct PlaylistPage {
     database: DataLink,
     hovered_song: Option<usize>,
     total_songs: usize,
-    downloaded: usize,
-    music_path: PathBuf
+    downloaded: usize
 }
 
 impl PlaylistPage {
-    pub fn new(playlist: Option<usize>, database: DataLink, music_path: PathBuf) -> Result<PlaylistPage, ()> {
+    pub fn new(playlist: Option<usize>, database: DataLink) -> Result<PlaylistPage, ()> {
 
         if playlist.is_none() {
             return Err(());
@@ -53,15 +50,14 @@ impl PlaylistPage {
             database,
             hovered_song: None,
             total_songs: 0,
-            downloaded: 0,
-            music_path
+            downloaded: 0
         })
     }
 }
 
 impl Page for PlaylistPage {
     fn view(
-        &self, current_song_downloads: &HashSet<String>, queued_downloads: &HashSet<Song>, thumbnail_manager: &ThumbnailManager
+        &self, downloads: &DownloadScheduler, thumbnail_manager: &ThumbnailManager
     ) -> Column<'_, Message> {
         let search_bar = Row::new().spacing(20).align_y(Vertical::Center).push(
             ResonateWidget::search_bar("Search...", &self.query)
@@ -76,18 +72,20 @@ impl Page for PlaylistPage {
 
         for song in &self.songs {
 
-            let is_downloading = current_song_downloads.contains(&song.yt_id);
-            let is_queued = queued_downloads.contains(song);
+            let downloading = downloads.progress(&song.yt_id);
+            let is_queued = downloads.is_queued(&song.yt_id);
+            let failed = downloads.error(&song.yt_id);
 
-            if is_downloading && is_queued {
+            if downloading.is_some() && is_queued {
                 println!("[ALERT] Queue / Download collision.");
             }
 
             let widget = ResonateWidget::song(
                 song,
                 thumbnail_manager,
-                is_downloading,
+                downloading,
                 is_queued,
+                failed,
                 Some(self.playlist.id),
                 if let Some(id) = self.hovered_song { id == song.id } else { false }
             );
@@ -169,12 +167,12 @@ impl Page for PlaylistPage {
                 for s in &mut self.songs {
                     if s.id == song.id {
                         self.downloaded += 1;
-                        s.load_music_path(self.music_path.clone());
+                        s.music_path = song.music_path.clone();
                     }
                 }
             }
 
-            Message::DownloadFailed(_) => {},
+            Message::DownloadFailed(_, _) => {},
 
             Message::RemoveSongFromPlaylist(song_id, _) => {
                 if let Some(idx) = self.songs.iter().enumerate().find_map(|song|
@@ -1,8 +1,7 @@
-use std::collections::HashSet;
-
 use iced::widget::Column;
 use iced::Task;
 
+use crate::backend::downloads::DownloadScheduler;
 use crate::backend::database_interface::DatabaseInterface;
 use crate::backend::thumbnail::ThumbnailManager;
 use crate::frontend::application::Page;
@@ -10,7 +9,7 @@ use crate::frontend::message::Message;
 use crate::frontend::widgets::ResonateWidget;
 use crate::frontend::message::PageType;
 
-use crate::backend::music::{Playlist, Song};
+use crate::backend::music::Playlist;
 use crate::backend::database_manager::DataLink;
 
 pub struct PlaylistsPage {
@@ -31,7 +30,7 @@ impl PlaylistsPage {
 
 impl Page for PlaylistsPage {
     fn view(
-        &self, _current_song_downloads: &HashSet<String>, _queued_downloads: &HashSet<Song>, _: &ThumbnailManager
+        &self, _: &DownloadScheduler, _: &ThumbnailManager
     ) -> Column<'_, Message> {
         let mut column = Column::new().spacing(20);
         for (i, value) in self.playlists.iter().enumerate() {
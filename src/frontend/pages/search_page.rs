@@ -8,6 +8,7 @@ use iced::widget::Row;
 use iced::Length;
 use iced::Task;
 
+use crate::backend::downloads::DownloadScheduler;
 use crate::backend::database_interface::DatabaseInterface;
 use crate::backend::thumbnail::ThumbnailManager;
 use crate::frontend::application::Page;
@@ -61,7 +62,7 @@ impl SearchPage {
 
 impl Page for SearchPage {
     fn view(
-        &self, current_song_downloads: &HashSet<String>, queued_downloads: &HashSet<Song>, thumbnail_manager: &ThumbnailManager
+        &self, downloads: &DownloadScheduler, thumbnail_manager: &ThumbnailManager
     ) -> Column<'_, Message> {
         let search_bar = Row::new()
             .push(
@@ -89,15 +90,17 @@ impl Page for SearchPage {
                     continue;
                 }
 
-                let is_downloading = current_song_downloads.contains(&song.yt_id);
-                let is_queued = queued_downloads.contains(song);
+                let downloading = downloads.progress(&song.yt_id);
+                let is_queued = downloads.is_queued(&song.yt_id);
+                let failed = downloads.error(&song.yt_id);
 
                 column = column.push(
                     ResonateWidget::song(
                         song,
                         thumbnail_manager,
-                        is_downloading,
+                        downloading,
                         is_queued,
+                        failed,
                         None,
                         false)
                         .on_press(Message::AddSongToPlaylist(song.clone(), match self.playlist.as_ref() {
@@ -150,7 +153,8 @@ impl Page for SearchPage {
                 self.search_notify = Some(SearchState::Searching);
 
                 if let Some(search_results) = self.search_results.as_mut() { search_results.clear(); }
-                for handle in &self.search_handles { handle.abort(); }
+                // Aborting drops the metadata pool, which kills its yt-dlp processes
+                for handle in self.search_handles.drain(..) { handle.abort(); }
 
                 let dlp_path = match self.directories.get_dlp_ref() {
                     Some(dlp_path) => dlp_path.to_path_buf(),
@@ -1,41 +1,72 @@
-use std::collections::HashSet;
-
+use iced::alignment::Vertical;
 use iced::widget::Column;
 use iced::widget::Row;
+use iced::widget::text;
+use iced::Length;
+use iced::Color;
 use iced::Task;
 
+use crate::backend::downloads::DownloadScheduler;
 use crate::backend::thumbnail::ThumbnailManager;
 use crate::frontend::application::Page;
 use crate::frontend::message::Message;
 use crate::frontend::message::PageType;
 use crate::frontend::widgets::ResonateWidget;
+use crate::frontend::widgets::ResonateColour;
 
-use crate::backend::music::Song;
 use crate::backend::settings::Secret;
+use crate::backend::settings::Settings;
+use crate::backend::loudness::Normalisation;
+use crate::backend::audio::QueueStartup;
+use crate::backend::music::AudioFormat;
+use crate::backend::filemanager::DataDir;
+use crate::backend::tools::ToolSource;
 
 pub struct SettingsPage {
     spotify_id: Option<String>,
     spotify_secret: Option<String>,
     fm_key: Option<String>,
     fm_secret: Option<String>,
-    fm_session: Option<String>
+    fm_session: Option<String>,
+    normalisation: Normalisation,
+    queue_startup: QueueStartup,
+    download_format: AudioFormat,
+    dlp_version: Option<String>,
+    dlp_pin: String,
+    dlp_status: Option<(String, Color)>,    // Outcome of the last update
+    dlp_updating: bool,
+    dlp_source: Option<ToolSource>,
+    ffmpeg_source: Option<ToolSource>,
+    retag_status: Option<String>,
+    retagging: bool
 }
 
 impl SettingsPage {
-    pub fn new() -> Self {
+    pub fn new(settings: &Settings, directories: &DataDir, dlp_version: Option<String>) -> Self {
         Self {
             spotify_id: None,
             spotify_secret: None,
             fm_key: None,
             fm_secret: None,
-            fm_session: None
+            fm_session: None,
+            normalisation: settings.normalisation,
+            queue_startup: settings.queue_startup,
+            download_format: settings.download_format,
+            dlp_version,
+            dlp_pin: settings.dlp_version.clone().unwrap_or_default(),
+            dlp_status: None,
+            dlp_updating: false,
+            dlp_source: directories.get_dlp_source(),
+            ffmpeg_source: directories.get_ffmpeg_source(),
+            retag_status: None,
+            retagging: false
         }
     }
 }
 
 impl Page for SettingsPage {
-    fn view(&self, _: &HashSet<String>, _: &HashSet<Song>, _: &ThumbnailManager) -> Column<'_, Message> {
-        Column::new().push(
+    fn view(&self, _: &DownloadScheduler, _: &ThumbnailManager) -> Column<'_, Message> {
+        Column::new().spacing(20).push(
             Row::new().spacing(10).push(
                 Column::new().spacing(20)
                     .push(
@@ -106,18 +137,122 @@ impl Page for SettingsPage {
                         Secret::FMSession(x.clone())
                 ))))
             )
+        ).push(
+            Row::new().spacing(10).align_y(Vertical::Center)
+                .push(text("LOUDNESS NORMALISATION").size(20).color(ResonateColour::text()).width(Length::Fill))
+                .push(
+                    ResonateWidget::toggle_text_button("OFF", self.normalisation == Normalisation::Off)
+                        .on_press(Message::SetNormalisation(Normalisation::Off))
+                ).push(
+                    ResonateWidget::toggle_text_button("TRACK", self.normalisation == Normalisation::Track)
+                        .on_press(Message::SetNormalisation(Normalisation::Track))
+                ).push(
+                    ResonateWidget::toggle_text_button("ALBUM", self.normalisation == Normalisation::Album)
+                        .on_press(Message::SetNormalisation(Normalisation::Album))
+                )
+        ).push(
+            Row::new().spacing(10).align_y(Vertical::Center)
+                .push(text("QUEUE ON STARTUP").size(20).color(ResonateColour::text()).width(Length::Fill))
+                .push(
+                    ResonateWidget::toggle_text_button("RESTORE", self.queue_startup == QueueStartup::Restore)
+                        .on_press(Message::SetQueueStartup(QueueStartup::Restore))
+                ).push(
+                    ResonateWidget::toggle_text_button("EMPTY", self.queue_startup == QueueStartup::Empty)
+                        .on_press(Message::SetQueueStartup(QueueStartup::Empty))
+                ).push(
+                    ResonateWidget::toggle_text_button("LIBRARY", self.queue_startup == QueueStartup::Library)
+                        .on_press(Message::SetQueueStartup(QueueStartup::Library))
+                )
+        ).push(
+            AudioFormat::ALL.into_iter().fold(
+                Row::new().spacing(10).align_y(Vertical::Center)
+                    .push(text("DOWNLOAD FORMAT").size(20).color(ResonateColour::text()).width(Length::Fill)),
+                |row, format| row.push(
+                    ResonateWidget::toggle_text_button(format.as_str(), self.download_format == format)
+                        .on_press(Message::SetDownloadFormat(format))
+                )
+            )
+        ).push(
+            Row::new().spacing(10).align_y(Vertical::Center)
+                .push(text("YT-DLP").size(20).color(ResonateColour::text()).width(Length::Fill))
+                .push_maybe(self.dlp_status.as_ref().map(|(status, colour)| text(status.as_str()).size(16).color(*colour)))
+                .push(
+                    text(match (self.dlp_version.as_ref(), self.dlp_source) {
+                        (Some(version), Some(source)) => format!("{version} ({})", source.as_str()),
+                        (Some(version), None) => version.clone(),
+                        (None, _) => String::from("Not installed")
+                    }).size(16).color(ResonateColour::darker())
+                ).push(
+                    ResonateWidget::search_bar("PIN VERSION", &self.dlp_pin).width(Length::Fixed(200f32))
+                        .on_input(Message::TextInput)
+                        .on_paste(Message::TextInput)
+                        .on_submit(Message::SetDLPPin(self.dlp_pin.clone()))
+                ).push(
+                    ResonateWidget::inline_button("UPDATE").on_press_maybe(
+                        // Copies found through PATH or an override are left to whoever installed them
+                        match self.dlp_version.is_some() && !self.dlp_updating && self.dlp_source == Some(ToolSource::Bundled) {
+                            true => Some(Message::UpdateDLP),
+                            false => None
+                        }
+                    )
+                )
+        ).push(
+            Row::new().spacing(10).align_y(Vertical::Center)
+                .push(text("FFMPEG").size(20).color(ResonateColour::text()).width(Length::Fill))
+                .push_maybe(self.retag_status.as_ref().map(|status| text(status.as_str()).size(16).color(ResonateColour::darker())))
+                .push(match self.ffmpeg_source {
+                    Some(source) => text(source.as_str()).size(16).color(ResonateColour::darker()),
+                    None => text("Not found, downloads can't be converted or tagged").size(16).color(ResonateColour::yellow())
+                }).push(
+                    // Writes the library's current titles, artists, albums and covers into the files
+                    ResonateWidget::inline_button("RE-TAG LIBRARY").on_press_maybe(
+                        match self.ffmpeg_source.is_some() && !self.retagging {
+                            true => Some(Message::RetagLibrary),
+                            false => None
+                        }
+                    )
+                )
         )
     }
 
     fn update(&mut self, message: Message) -> Task<Message> {
-        if let Message::ChangeSecret(secret) = message {
-            match secret {
+        match message {
+            Message::ChangeSecret(secret) => match secret {
                 Secret::FMKey(new_val) => self.fm_key = Some(new_val),
                 Secret::FMSecret(new_val) => self.fm_secret = Some(new_val),
                 Secret::FMSession(new_val) => self.fm_session = Some(new_val),
                 Secret::SpotifyID(new_val) => self.spotify_id = Some(new_val),
                 Secret::SpotifySecret(new_val) => self.spotify_secret = Some(new_val),
+            },
+            Message::SetNormalisation(normalisation) => self.normalisation = normalisation,
+            Message::SetQueueStartup(queue_startup) => self.queue_startup = queue_startup,
+            Message::SetDownloadFormat(format) => self.download_format = format,
+            Message::TextInput(pin) => self.dlp_pin = pin,
+            Message::SetDLPPin(pin) => self.dlp_pin = pin,
+            Message::DLPVersion(version) => self.dlp_version = version,
+            Message::UpdateDLP => {
+                self.dlp_updating = true;
+                self.dlp_status = Some((String::from("Updating..."), ResonateColour::yellow()));
+            }
+            Message::RetagLibrary => {
+                self.retagging = true;
+                self.retag_status = Some(String::from("Tagging..."));
+            }
+            Message::LibraryRetagged(tagged, total) => {
+                self.retagging = false;
+                self.retag_status = Some(format!("Tagged {tagged} of {total}"));
+            }
+            Message::DLPUpdated(res) => {
+                self.dlp_updating = false;
+                self.dlp_status = Some(match res {
+                    Ok(version) => {
+                        self.dlp_version = Some(version);
+                        (String::from("Updated"), ResonateColour::green())
+                    }
+                    Err(_) => (String::from("Update failed, kept previous version"), ResonateColour::red())
+                });
             }
+            _ => {}
         }
         Task::none()
     }
@@ -1,25 +1,35 @@
 use tray_icon::{
-    menu::{Menu, MenuEvent, MenuItem}, TrayIcon, TrayIconBuilder
+    menu::{CheckMenuItem, Menu, MenuEvent, MenuId, MenuItem, PredefinedMenuItem, Submenu}, TrayIcon, TrayIconBuilder
 };
 
 use async_channel::unbounded;
 use async_channel::Receiver;
 
 use std::thread;
+use std::time::Instant;
 use image::GenericImageView;
 
+use crate::backend::audio::QueueFramework;
+
 const ICON_BYTES: &[u8] = include_bytes!("assets/icons/icon.png");
 
+/// Sleep timer lengths offered in the tray, in minutes
+const SLEEP_TIMER_OPTIONS: [u64; 5] = [15, 30, 45, 60, 90];
+
 #[derive(Debug)]
 pub enum TrayMessage {
     OpenMain,
-    Quit
+    Quit,
+    SleepTimer(Option<u64>),         // Minutes, None cancels
+    ToggleStopAfterCurrent
 }
 
 pub struct SimpleTray {
     _tray_icon: TrayIcon,
     _event_thread: thread::JoinHandle<()>,
-    out: Option<Receiver<TrayMessage>>
+    out: Option<Receiver<TrayMessage>>,
+    stop_after_current_item: CheckMenuItem,
+    cancel_sleep_item: MenuItem
 }
 
 impl SimpleTray {
@@ -28,6 +38,22 @@ impl SimpleTray {
         self.out.take()
     }
 
+    /// Keep the menu in step with the queue, which can also be changed from the main window
+    pub fn sync(&self, queue_state: &QueueFramework) {
+        self.stop_after_current_item.set_checked(queue_state.stop_after_current);
+        match queue_state.sleep_until {
+            Some(deadline) => {
+                let minutes = deadline.saturating_duration_since(Instant::now()).as_secs().div_ceil(60);
+                self.cancel_sleep_item.set_text(format!("Cancel sleep timer ({minutes} min left)"));
+                self.cancel_sleep_item.set_enabled(true);
+            }
+            None => {
+                self.cancel_sleep_item.set_text("Cancel sleep timer");
+                self.cancel_sleep_item.set_enabled(false);
+            }
+        }
+    }
+
     pub fn new() -> Self {
     let img = image::load_from_memory(ICON_BYTES).expect("Failed to load embedded image");
 
@@ -42,6 +68,20 @@ impl SimpleTray {
     let open_item = MenuItem::new("Open", true, None);
     menu.append(&open_item).expect("Failed to append menu item");
 
+    let stop_after_current_item = CheckMenuItem::new("Stop after current", true, false, None);
+    menu.append(&stop_after_current_item).expect("Failed to append menu item");
+
+    let sleep_menu = Submenu::new("Sleep timer", true);
+    let sleep_items: Vec<(MenuId, u64)> = SLEEP_TIMER_OPTIONS.iter().map(|minutes| {
+        let item = MenuItem::new(format!("{minutes} minutes"), true, None);
+        sleep_menu.append(&item).expect("Failed to append menu item");
+        (item.id().clone(), *minutes)
+    }).collect();
+    let cancel_sleep_item = MenuItem::new("Cancel sleep timer", false, None);
+    sleep_menu.append(&PredefinedMenuItem::separator()).expect("Failed to append menu item");
+    sleep_menu.append(&cancel_sleep_item).expect("Failed to append menu item");
+    menu.append(&sleep_menu).expect("Failed to append menu item");
+
     let close_item = MenuItem::new("Quit", true, None);
     menu.append(&close_item).expect("Failed to append menu item");
 
@@ -55,6 +95,8 @@ impl SimpleTray {
 
     let open_item_id = open_item.id().clone();
     let close_item_id = close_item.id().clone();
+    let stop_after_current_id = stop_after_current_item.id().clone();
+    let cancel_sleep_id = cancel_sleep_item.id().clone();
 
     let (sender, receiver) = unbounded();
 
@@ -66,8 +108,14 @@ impl SimpleTray {
                         Some(TrayMessage::OpenMain)
                     } else if evt.id == close_item_id {
                         Some(TrayMessage::Quit)
+                    } else if evt.id == stop_after_current_id {
+                        Some(TrayMessage::ToggleStopAfterCurrent)
+                    } else if evt.id == cancel_sleep_id {
+                        Some(TrayMessage::SleepTimer(None))
                     } else {
-                        None
+                        sleep_items.iter()
+                            .find(|(id, _)| *id == evt.id)
+                            .map(|(_, minutes)| TrayMessage::SleepTimer(Some(*minutes)))
                     } {
                         let _ = sender.send_blocking(message);
                     }
@@ -78,7 +126,9 @@ impl SimpleTray {
         Self {
             _tray_icon: tray_icon,
             _event_thread: event_thread,
-            out: Some(receiver)
+            out: Some(receiver),
+            stop_after_current_item,
+            cancel_sleep_item
         }
     }
 }
@@ -1,9 +1,12 @@
 use std::collections::HashSet;
+use std::time::Duration;
+use std::time::Instant;
 
 use iced::alignment::{Horizontal, Vertical};
 use iced::advanced::svg::Handle;
 use iced::widget::text::LineHeight;
 use iced::widget::{button, progress_bar, slider, text_input, toggler, vertical_space, Button, Slider, Space, Stack};
+use iced::widget::{pick_list, vertical_slider};
 use iced::widget::scrollable::{Direction, Scroller};
 use iced::widget::{container, image, scrollable, text, Column, Container, Row, Scrollable, TextInput, svg, ProgressBar};
 use iced::{Background, Border, Color, Element, Length, Pixels, Shadow};
@@ -15,6 +18,15 @@ use crate::frontend::pages::search_page::SearchState;
 
 use crate::backend::music::{Playlist, Song};
 use crate::backend::audio::{AudioTask, ProgressUpdate, QueueFramework};
+use crate::backend::equalizer::{EqualizerPreset, Gains, FREQUENCIES, MAX_GAIN};
+use crate::backend::tempo::{MIN_SPEED, MAX_SPEED};
+use crate::backend::visualiser::VisualUpdate;
+use crate::backend::waveform::Waveform;
+use crate::backend::looper::Section;
+use crate::backend::web::DownloadProgress;
+use crate::backend::error::DownloadError;
+use crate::backend::downloads::DownloadEntry;
+use crate::backend::downloads::DownloadState;
 
 use super::application::Mode;
 
@@ -189,6 +201,27 @@ impl ResonateStyle {
         }
     }
 
+    pub fn slider() -> slider::Style {
+        slider::Style {
+            rail: slider::Rail {
+                backgrounds: (
+                    Background::Color(ResonateColour::colour()),
+                    Background::Color(ResonateColour::accent())
+                ),
+                width: 15f32,
+                border: Border::default().rounded(10)
+            },
+            handle: slider::Handle {
+                shape: slider::HandleShape::Circle {
+                    radius: 10f32
+                },
+                background: Background::Color(ResonateColour::colour()),
+                border_width: 0f32,
+                border_color: ResonateColour::colour()
+            }
+        }
+    }
+
     pub fn icon_button_with_background(status: iced::widget::button::Status, on: bool) -> button::Style {
         button::Style {
             background: Some(iced::Background::Color(
@@ -205,6 +238,12 @@ impl ResonateStyle {
         }
     }
 
+    pub fn level_bar(colour: Color) -> container::Style {
+        container::Style::default()
+            .background(Background::Color(colour))
+            .border(Border::default().rounded(2))
+    }
+
     pub fn progress_bar() -> progress_bar::Style {
         progress_bar::Style {
             background: Background::Color(ResonateColour::accent()),
@@ -286,7 +325,7 @@ impl ResonateWidget {
                     for song in songs.iter() {
                         if existing_songs.contains(&song.id) { continue; }
                         column = column.push(
-                            Self::song(song, thumbnail_manager, false, false, None, false)
+                            Self::song(song, thumbnail_manager, None, false, None, None, false)
                                 .on_press(Message::AddSongToPlaylist(song.clone(), playlist_id))
                         )
                     }
@@ -305,11 +344,37 @@ impl ResonateWidget {
                 let mut column = Column::new().spacing(10);
                 let queue_items = match queue_state.as_ref() {
                     Some(queue_state) => {
+                        let up_next_end = queue_state.position + 1 + queue_state.up_next;
+                        let last = queue_state.songs.len().saturating_sub(1);
+
                         for (idx, song) in queue_state.songs.iter().enumerate() {
-                            column = column.push(
-                                Self::simple_song(song, thumbnail_manager, idx == queue_state.position)
-                                    .on_press(Message::AudioTask(AudioTask::Move(idx)))
-                            )
+                            if idx == queue_state.position + 1 && queue_state.up_next > 0 {
+                                column = column.push(Self::queue_section("UP NEXT"));
+                            }
+                            if idx == up_next_end && idx > queue_state.position {
+                                column = column.push(Self::queue_section("NEXT FROM QUEUE"));
+                            }
+
+                            let entry = Self::simple_song(song, thumbnail_manager, idx == queue_state.position)
+                                .on_press(Message::AudioTask(AudioTask::Move(idx)));
+
+                            // Only upcoming songs can be reordered, history stays as it was played
+                            column = column.push(match idx > queue_state.position {
+                                true => Row::new().spacing(5).align_y(Vertical::Center).push(entry).push(
+                                    Column::new().push(
+                                        Self::inline_button("▲").on_press_maybe(match idx > queue_state.position + 1 {
+                                            true => Some(Message::AudioTask(AudioTask::MoveItem(idx, idx - 1))),
+                                            false => None
+                                        })
+                                    ).push(
+                                        Self::inline_button("▼").on_press_maybe(match idx < last {
+                                            true => Some(Message::AudioTask(AudioTask::MoveItem(idx, idx + 1))),
+                                            false => None
+                                        })
+                                    )
+                                ).into(),
+                                false => Element::from(entry)
+                            });
                         }
                         column.into()
                     },
@@ -321,6 +386,10 @@ impl ResonateWidget {
         ).width(Length::FillPortion(1)).height(Length::FillPortion(45)).into()
     }
 
+    fn queue_section<'a>(label: &'a str) -> Element<'a, Message> {
+        text(label).size(16).color(ResonateColour::darker()).into()
+    }
+
     #[allow(clippy::too_many_arguments)]
     pub fn control_bar<'a>(
         queue_state: Option<&'a QueueFramework>,
@@ -328,9 +397,11 @@ impl ResonateWidget {
         last_page: (PageType, Option<usize>),
         progress_update: Option<ProgressUpdate>,
         volume: f32,
+        (speed, preserve_pitch): (f32, bool),
         default_queue: &'a QueueFramework,
         mode: Mode,
-        show_queue: bool
+        show_queue: bool,
+        show_equalizer: bool
     ) -> Element<'a, Message> {
 
         let (real, queue_state) = match queue_state {
@@ -379,6 +450,13 @@ impl ResonateWidget {
                                 ).on_press(
                                     Message::AudioTask(AudioTask::ToggleRepeat)
                                 )
+                            ).push(
+                                Self::toggle_button_widget(
+                                    crate::frontend::assets::shuffle(),
+                                    queue_state.shuffle
+                                ).on_press(
+                                    Message::AudioTask(AudioTask::SetShuffle(!queue_state.shuffle))
+                                )
                             )
                         ).align_x(Horizontal::Center).width(Length::FillPortion(1))
                     )
@@ -407,24 +485,24 @@ impl ResonateWidget {
                     ).push(
                         Slider::new(0f32..=2f32, volume,
                             |value| Message::AudioTask(AudioTask::SetVolume(value))
-                        ).style(|_,_| slider::Style {
-                            rail: slider::Rail {
-                                backgrounds: (
-                                    Background::Color(ResonateColour::colour()),
-                                    Background::Color(ResonateColour::accent())
-                                ),
-                                width: 15f32,
-                                border: Border::default().rounded(10)
-                            },
-                            handle: slider::Handle {
-                                shape: slider::HandleShape::Circle {
-                                    radius: 10f32
-                                },
-                                background: Background::Color(ResonateColour::colour()),
-                                border_width: 0f32,
-                                border_color: ResonateColour::colour()
-                            }
-                        }).step(0.01f32)
+                        ).style(|_,_| ResonateStyle::slider()).step(0.01f32)
+                    ).push(
+                        Self::toggle_text_button("EQ", show_equalizer)
+                            .on_press(Message::ToggleEqualizer(!show_equalizer))
+                    )
+                ).push(
+                    Row::new().spacing(10).align_y(Vertical::Center).push(
+                        Self::toggle_text_button("1x", speed == 1f32)
+                            .on_press(Message::ResetSpeed)
+                    ).push(
+                        Slider::new(MIN_SPEED..=MAX_SPEED, speed,
+                            |value| Message::AudioTask(AudioTask::SetSpeed(value))
+                        ).on_release(Message::SaveSettings).style(|_,_| ResonateStyle::slider()).step(0.05f32)
+                    ).push(
+                        text(format!("{speed:.2}x")).size(16).color(ResonateColour::text())
+                    ).push(
+                        Self::toggle_text_button("PITCH", preserve_pitch)
+                            .on_press(Message::AudioTask(AudioTask::SetPreservePitch(!preserve_pitch)))
                     )
                 ).push(
                     Row::new().spacing(10).align_y(Vertical::Center).push(
@@ -432,6 +510,11 @@ impl ResonateWidget {
                             .on_press(Message::LoadPage(
                                 PageType::Settings, None
                             ))
+                    ).push(
+                        Self::button_widget(crate::frontend::assets::downloading_icon())
+                            .on_press(Message::LoadPage(
+                                PageType::Downloads, None
+                            ))
                     ).push(
                         button("Normal")
                             .on_press(Message::SetMode(Mode::Normal))
@@ -468,12 +551,71 @@ impl ResonateWidget {
         ).style(move |_,status| ResonateStyle::icon_button_with_background(status, state))
     }
 
+    pub fn toggle_text_button(label: &str, state: bool) -> Button<'_, Message> {
+        button(text(label).size(20)).style(move |_, status| ResonateStyle::icon_button_with_background(status, state))
+    }
+
+    /// One vertical slider per band, with a preset picker and a field to save the current curve
+    pub fn equalizer_panel<'a>(
+        gains: &Gains,
+        presets: &'a [EqualizerPreset],
+        preset_name: &str
+    ) -> Element<'a, Message> {
+        let selected = presets.iter().find(|preset| preset.gains == *gains);
+
+        let bands = gains.iter().zip(FREQUENCIES).enumerate().fold(
+            Row::new().spacing(10).height(Length::Fixed(200f32)),
+            |row, (band, (gain, frequency))| row.push(
+                Column::new().spacing(5).align_x(Horizontal::Center).push(
+                    vertical_slider(-MAX_GAIN..=MAX_GAIN, *gain, move |value| Message::SetEqualizerBand(band, value))
+                        .on_release(Message::SaveSettings)
+                        .style(|_,_| ResonateStyle::slider())
+                        .step(0.5f32)
+                        .height(Length::Fill)
+                ).push(
+                    text(match frequency >= 1000f32 {
+                        true => format!("{}k", frequency / 1000f32),
+                        false => format!("{frequency}")
+                    }).size(12).color(ResonateColour::darker())
+                )
+            )
+        );
+
+        Container::new(
+            Column::new().spacing(10).push(
+                Row::new().spacing(10).align_y(Vertical::Center).push(
+                    pick_list(presets, selected.cloned(), Message::LoadEqualizerPreset)
+                        .placeholder("Custom")
+                ).push_maybe(
+                    selected.and_then(|preset| preset.id).map(|id|
+                        Self::inline_button("Delete").on_press(Message::DeleteEqualizerPreset(id))
+                    )
+                )
+            ).push(bands).push(
+                Row::new().spacing(10).align_y(Vertical::Center).push(
+                    text_input("Preset name", preset_name)
+                        .on_input(Message::EqualizerPresetName)
+                        .on_submit(Message::SaveEqualizerPreset)
+                        .style(|_, status| ResonateStyle::search_bar(status))
+                        .width(Length::Fixed(160f32))
+                ).push(
+                    button("Save")
+                        .on_press_maybe(match preset_name.trim().is_empty() {
+                            true => None,
+                            false => Some(Message::SaveEqualizerPreset)
+                        })
+                        .style(|_, status| ResonateStyle::hightlighted_button_wrapper(status))
+                )
+            )
+        ).style(|_| ResonateStyle::list_container()).padding(10).into()
+    }
+
     pub fn header(value: &str) -> Element<'_, Message> {
         text(value).size(30).color(ResonateColour::colour()).width(Length::Shrink).into()
     }
 
-    pub fn inline_button(text: &str) -> Button<'_, Message> {
-        button(text).style(|_, _| button::Style {
+    pub fn inline_button<'a>(label: impl text::IntoFragment<'a>) -> Button<'a, Message> {
+        button(text(label)).style(|_, _| button::Style {
             background: None,
             text_color: ResonateColour::darker(),
             border: Border::default(),
@@ -590,8 +732,9 @@ impl ResonateWidget {
     pub fn song<'a>(
         song: &'a Song,
         thumbnail_manager: &ThumbnailManager,
-        is_downloading: bool,
+        downloading: Option<&DownloadProgress>,
         is_queued: bool,
+        failed: Option<&DownloadError>,
         playlist_id: Option<usize>,
         show_buttons: bool
     ) -> Button<'a, Message> {
@@ -613,7 +756,7 @@ impl ResonateWidget {
                     ).push(
                         Row::new().spacing(10).align_y(Vertical::Center)
                             .push(
-                                if is_downloading {
+                                if downloading.is_some() {
                                     Self::svg(crate::frontend::assets::downloading_icon(), ResonateColour::yellow())
                                 }
                                 else if is_downloaded {
@@ -627,6 +770,11 @@ impl ResonateWidget {
                                 }
                             )
                             .push(text(&song.artist).width(Length::FillPortion(2)))
+                            .push_maybe(downloading.map(Self::download_progress))
+                            .push_maybe(
+                                failed.filter(|_| downloading.is_none() && !is_downloaded)
+                                    .map(|error| text(error.as_str()).size(14).color(ResonateColour::red()))
+                            )
                     )
             ).push(
                 text(match &song.album {
@@ -635,6 +783,22 @@ impl ResonateWidget {
                 }).width(Length::FillPortion(3))
             ).push(
                 text(song.display_duration()).width(Length::FillPortion(1))
+            ).push_maybe(
+                match downloading.is_some() || is_queued {
+                    true => Some(
+                        Self::inline_button("CANCEL")
+                            .on_press(Message::CancelDownload(song.clone()))
+                    ),
+                    false => None
+                }
+            ).push_maybe(
+                match show_buttons && is_downloaded && playlist_id.is_some() {
+                    true => Some(
+                        Self::inline_button("PLAY NEXT")
+                            .on_press(Message::AudioTask(AudioTask::Insert(song.clone())))
+                    ),
+                    false => None
+                }
             ).push_maybe(
                 match playlist_id {
                     Some(playlist_id) =>
@@ -650,6 +814,69 @@ impl ResonateWidget {
         ).padding(10).width(Length::Fill)).style(|_, state| ResonateStyle::button_wrapper(state))
     }
 
+    /// Percentage, speed and time left of a running download
+    pub fn download_progress<'a>(progress: &DownloadProgress) -> Element<'a, Message> {
+        let details = match (progress.retrying, progress.post_processing) {
+            (Some((attempt, error)), _) => format!("{} · retrying after attempt {attempt}", error.as_str()),
+            (None, true) => String::from("Processing"),
+            (None, false) => [
+                Some(format!("{:.0}%", progress.percent)),
+                progress.size.clone(),
+                progress.speed.clone(),
+                progress.eta.as_ref().map(|eta| format!("{eta} left"))
+            ].into_iter().flatten().collect::<Vec<String>>().join(" · ")
+        };
+
+        Row::new().spacing(10).align_y(Vertical::Center).push(
+            ProgressBar::new(0f32..=100f32, progress.percent)
+                .width(Length::Fixed(120f32))
+                .height(Length::Fixed(6f32))
+                .style(|_| ResonateStyle::progress_bar())
+        ).push(
+            text(details).size(14).color(ResonateColour::yellow())
+        ).into()
+    }
+
+    /// One row of the Downloads page
+    pub fn download_entry<'a>(entry: &DownloadEntry, progress: Option<&DownloadProgress>) -> Element<'a, Message> {
+        let status: Element<'a, Message> = match (entry.state, progress) {
+            (DownloadState::Active, Some(progress)) => Self::download_progress(progress),
+            (DownloadState::Active, None) => text("Starting").size(14).color(ResonateColour::yellow()).into(),
+            (DownloadState::Queued, _) => text("Queued").size(14).color(ResonateColour::yellow()).into(),
+            (DownloadState::Failed(error), _) => text(error.as_str()).size(14).color(ResonateColour::red()).into(),
+            (DownloadState::Done, _) => text(
+                entry.size.map(Self::file_size).unwrap_or(String::from("Done"))
+            ).size(14).color(ResonateColour::green()).into()
+        };
+
+        let action = match entry.state {
+            DownloadState::Active | DownloadState::Queued => Some(
+                Self::inline_button("CANCEL").on_press(Message::CancelDownload(entry.song.clone()))
+            ),
+            DownloadState::Failed(_) => Some(
+                Self::inline_button("RETRY").on_press(Message::Download(entry.song.clone()))
+            ),
+            DownloadState::Done => None
+        };
+
+        Container::new(
+            Row::new().spacing(20).align_y(Vertical::Center).push(
+                Column::new().spacing(5).width(Length::FillPortion(3))
+                    .push(text(entry.song.title.clone()).size(18).color(ResonateColour::text()))
+                    .push(text(entry.song.artist.clone()).size(14))
+            ).push(
+                Container::new(status).width(Length::FillPortion(3))
+            ).push_maybe(action)
+        ).style(|_| ResonateStyle::list_container()).padding(10).width(Length::Fill).into()
+    }
+
+    fn file_size(bytes: u64) -> String {
+        match bytes {
+            bytes if bytes >= 1 << 20 => format!("{:.1} MiB", bytes as f64 / (1 << 20) as f64),
+            bytes => format!("{:.0} KiB", bytes as f64 / 1024f64)
+        }
+    }
+
     pub fn padded_scrollable(element: Element<'_, Message>) -> Scrollable<'_, Message> {
             Scrollable::new(
                 element
@@ -680,9 +907,12 @@ impl ResonateWidget {
         back: (PageType, Option<usize>),
         progress: Option<ProgressUpdate>,
         volume: f32,
+        speed: (f32, bool),
         default_queue: &'a QueueFramework,
+        equalizer: Option<Element<'a, Message>>,
         element: Element<'a, Message>,
     ) -> Element<'a, Message> {
+        let show_equalizer = equalizer.is_some();
         Stack::new()
         .push_maybe(
             current.map(|song|
@@ -704,6 +934,9 @@ impl ResonateWidget {
                         _ => None
                     }
                 ))
+            .push_maybe(
+                equalizer.map(|panel| Row::new().push(Space::with_width(Length::Fill)).push(panel))
+            )
             .push(
                 ResonateWidget::control_bar(
                     queue_state,
@@ -711,9 +944,11 @@ impl ResonateWidget {
                     back,
                     progress,
                     volume,
+                    speed,
                     default_queue,
                     mode,
-                    show_queue
+                    show_queue,
+                    show_equalizer
                 )
             )
         ).push_maybe(
@@ -728,6 +963,7 @@ impl ResonateWidget {
                     .on_press(Message::AudioTask(AudioTask::ClearQueue))
                 )
             )
+            .push(ResonateWidget::sleep_controls(queue_state.unwrap_or(default_queue)))
             .push(
                 ResonateWidget::queue_bar(
                     queue_state,
@@ -737,6 +973,35 @@ impl ResonateWidget {
         ).into()
     }
 
+    /// Stop after current toggle and sleep timer, shown above the queue
+    pub fn sleep_controls<'a>(queue_state: &QueueFramework) -> Element<'a, Message> {
+        let row = Row::new().spacing(10).align_y(Vertical::Center).push(
+            Self::toggle_text_button("STOP AFTER", queue_state.stop_after_current)
+                .on_press(Message::AudioTask(AudioTask::SetStopAfterCurrent(!queue_state.stop_after_current)))
+        ).push(
+            Self::toggle_text_button("AUTOPLAY", queue_state.autoplay)
+                .on_press(Message::AudioTask(AudioTask::SetAutoplay(!queue_state.autoplay)))
+        ).push(Space::with_width(Length::Fill));
+
+        match queue_state.sleep_until {
+            Some(deadline) => {
+                let remaining = deadline.saturating_duration_since(Instant::now()).as_secs();
+                row.push(
+                    text(format!("Sleep in {}:{:02}", remaining / 60, remaining % 60)).size(16).color(ResonateColour::text())
+                ).push(
+                    Self::inline_button("Cancel").on_press(Message::AudioTask(AudioTask::SetSleepTimer(None)))
+                )
+            }
+            None => [15u64, 30, 60].into_iter().fold(
+                row.push(text("SLEEP").size(16).color(ResonateColour::darker())),
+                |row, minutes| row.push(
+                    Self::inline_button(format!("{minutes}m"))
+                        .on_press(Message::AudioTask(AudioTask::SetSleepTimer(Some(Duration::from_secs(minutes * 60)))))
+                )
+            )
+        }.into()
+    }
+
     pub fn lyrics(lyrics: &str) -> Element<'_, Message> {
         let lyrics_list: Vec<&str> = lyrics.split('\n').collect();
         let mut column = Column::new().align_x(Horizontal::Center).spacing(0).width(Length::Shrink);
@@ -768,8 +1033,14 @@ impl ResonateWidget {
     }
 
     pub fn now_playing_view<'a>(
-        thumbnail_manager: &'a ThumbnailManager, now_playing: &'a Song, progress_update: Option<ProgressUpdate>
+        thumbnail_manager: &'a ThumbnailManager, now_playing: &'a Song, progress_update: Option<ProgressUpdate>,
+        visual: Option<&'a VisualUpdate>, waveform: Option<&'a Waveform>
     ) -> Column<'a, Message> {
+        let (current, length) = match progress_update {
+            Some(ProgressUpdate::Seconds(current, length)) => (current, length),
+            _ => (0f32, now_playing.duration.as_secs_f32())
+        };
+
         Column::new().align_x(Horizontal::Center).height(Length::Fill).width(Length::Fill)
         .push(
             Row::new().spacing(10).align_y(Vertical::Center).height(Length::Fill)
@@ -777,23 +1048,121 @@ impl ResonateWidget {
                 image(thumbnail_manager.get_thumbnail_path_blocking(now_playing.clone()).large())
                     .width(Length::Shrink).height(Length::Fixed(400f32))
             ).push(
-                Column::new().push(
+                Column::new().spacing(10).push(
                     text(&now_playing.title).size(32f32).color(ResonateColour::text())
                 ).push(
                     text(&now_playing.artist).size(20f32).color(ResonateColour::text())
                 ).push(
-                    ProgressBar::new(0f32..=1000f32, match progress_update {
-                        Some(update) => match update {
-                            ProgressUpdate::Nothing => 0f32,
-                            ProgressUpdate::Seconds(current, length) => (current / length) * 1000f32
-                        },
-                        None => 0f32
-                    }).width(Length::FillPortion(1)).style(|_| ResonateStyle::progress_bar())
-                            .height(Length::Fixed(45f32))
-                )
+                    match waveform {
+                        Some(waveform) => Self::waveform_seek_bar(waveform, current, length),
+                        None => ProgressBar::new(0f32..=1000f32, (current / length) * 1000f32)
+                            .width(Length::FillPortion(1)).style(|_| ResonateStyle::progress_bar())
+                            .height(Length::Fixed(45f32)).into()
+                    }
+                ).push_maybe(visual.map(Self::visualiser))
 
                 .align_x(Horizontal::Left).width(Length::Shrink)
             )
         )
     }
+
+    fn timestamp(seconds: f32) -> String {
+        let seconds = seconds.max(0f32) as u64;
+        format!("{}:{:02}", seconds / 60, seconds % 60)
+    }
+
+    /// A-B markers for the current song and the sections saved against it
+    pub fn loop_panel<'a>(
+        loop_section: Option<(f32, f32)>,
+        loop_start: Option<f32>,
+        sections: &'a [Section],
+        section_name: &str
+    ) -> Element<'a, Message> {
+        let markers = Row::new().spacing(10).align_y(Vertical::Center).push(
+            Self::toggle_text_button("A", loop_start.is_some() || loop_section.is_some())
+                .on_press(Message::SetLoopStart)
+        ).push(
+            Self::toggle_text_button("B", loop_section.is_some())
+                .on_press_maybe(match loop_start.is_some() || loop_section.is_some() {
+                    true => Some(Message::SetLoopEnd),
+                    false => None
+                })
+        ).push(
+            text(match (loop_section, loop_start) {
+                (_, Some(start)) => format!("From {}", Self::timestamp(start)),
+                (Some((start, end)), None) => format!("Looping {} - {}", Self::timestamp(start), Self::timestamp(end)),
+                (None, None) => String::from("No loop")
+            }).size(16).color(ResonateColour::text())
+        ).push_maybe(
+            loop_section.map(|_| Self::inline_button("Clear").on_press(Message::AudioTask(AudioTask::SetLoop(None))))
+        );
+
+        let save = Row::new().spacing(10).align_y(Vertical::Center).push(
+            text_input("Section name", section_name)
+                .on_input(Message::SectionName)
+                .on_submit(Message::SaveSection)
+                .style(|_, status| ResonateStyle::search_bar(status))
+                .width(Length::Fixed(160f32))
+        ).push(
+            button("Save")
+                .on_press_maybe(loop_section.map(|_| Message::SaveSection))
+                .style(|_, status| ResonateStyle::hightlighted_button_wrapper(status))
+        );
+
+        sections.iter().fold(
+            Column::new().spacing(10).push(markers).push(save),
+            |column, section| column.push(
+                Row::new().spacing(10).align_y(Vertical::Center).push(
+                    Self::toggle_text_button(&section.name, loop_section == Some((section.start, section.end)))
+                        .on_press(Message::AudioTask(AudioTask::SetLoop(Some((section.start, section.end)))))
+                ).push(
+                    text(format!("{} - {}", Self::timestamp(section.start), Self::timestamp(section.end)))
+                        .size(16).color(ResonateColour::darker())
+                ).push_maybe(
+                    section.id.map(|id| Self::inline_button("Delete").on_press(Message::DeleteSection(id)))
+                )
+            )
+        ).into()
+    }
+
+    /// Spectrum bands on the left, the rolling peak history on the right
+    pub fn visualiser(visual: &VisualUpdate) -> Element<'_, Message> {
+        let bars = |values: &[f32], width: f32, colour: Color| values.iter().fold(
+            Row::new().spacing(2).align_y(Vertical::Bottom).height(Length::Fixed(100f32)),
+            |row, value| row.push(
+                Container::new(Space::new(Length::Fixed(width), Length::Fixed(2f32 + value * 98f32)))
+                    .style(move |_| ResonateStyle::level_bar(colour))
+            )
+        );
+
+        Row::new().spacing(20)
+            .push(bars(&visual.bands, 8f32, ResonateColour::colour()))
+            .push(bars(&visual.peaks, 2f32, ResonateColour::darker()))
+            .into()
+    }
+
+    /// Waveform overview that seeks to wherever it is clicked
+    pub fn waveform_seek_bar(waveform: &Waveform, current: f32, length: f32) -> Element<'_, Message> {
+        let played = match length > 0f32 {
+            true => (current / length * waveform.len() as f32) as usize,
+            false => 0
+        };
+
+        waveform.iter().enumerate().fold(
+            Row::new().spacing(1).align_y(Vertical::Center).height(Length::Fixed(60f32)),
+            |row, (idx, point)| row.push(
+                button(Space::new(Length::Fixed(3f32), Length::Fixed(2f32 + point * 56f32)))
+                    .padding(0)
+                    .style(move |_, _| button::Style {
+                        background: Some(Background::Color(match idx < played {
+                            true => ResonateColour::colour(),
+                            false => ResonateColour::darker()
+                        })),
+                        border: Border::default().rounded(2),
+                        ..button::Style::default()
+                    })
+                    .on_press(Message::AudioTask(AudioTask::Seek(length * idx as f32 / waveform.len() as f32)))
+            )
+        ).into()
+    }
 }
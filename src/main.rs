@@ -27,7 +27,6 @@ fn main() -> Result<(), iced::Error> {
             Message::DownloadDLP.task(),
             Message::LoadSecrets.task(),
             Message::LoadAllPlaylists.task(),
-            Message::LoadEverythingIntoQueue.task(),
             Message::OpenMain.task(),
             Message::StartTray.task(),
             Message::Lyrics(LyricMsg::SpawnCollector).task(),